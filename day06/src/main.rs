@@ -7,39 +7,70 @@ type Position = (i32, i32);
 
 fn main() -> std::io::Result<()> {
     let mut map = Map::from_file("input/day06.txt")?;
-    let initial_guard_pos = map.guard_pos;
-
-    while map.move_guard() {};
+    let initial_guards = map.guards.clone();
 
     println!("Part1: {}", part1(&mut map));
-    println!("Part2: {}", part2(&map, initial_guard_pos));
+    for (i, visited) in map.visited.iter().enumerate() {
+        println!("  guard {}: {} positions{}", i, visited.len(), if map.looping[i] { " (loops)" } else { "" });
+    }
+    println!("Part2: {}", part2(&map, &initial_guards));
+
+    match std::env::args().nth(1).as_deref() {
+        Some("csv") => print!("{}", map.path_csv()),
+        Some("json") => println!("{}", map.path_json()),
+        Some("render") => {
+            // render X Y draws the patrols with an extra obstruction at X,Y instead
+            let coordinates: Vec<String> = std::env::args().skip(2).take(2).collect();
+            match coordinates.iter().map(|c| c.parse::<i32>()).collect::<Result<Vec<_>, _>>().as_deref() {
+                Ok([]) => print!("{}", map.render(None)),
+                Ok(&[x, y]) if map.in_bounds((x, y)) => print!("{}", render_obstructed(&map, &initial_guards, (x, y))),
+                _ => eprintln!("render takes no position or an X Y position on the map"),
+            }
+        },
+        _ => {}
+    }
 
     Ok(())
 }
 
 fn part1(map: &mut Map) -> usize {
-    while map.move_guard() {}
+    while map.move_guards() {}
 
-    map.visited.len()
+    map.visited_union().len()
 }
 
-fn part2(map: &Map, start_pos: Position) -> usize {
+// Counts the obstructions that trap at least one of the guards in a loop. Only positions
+// some guard actually walks through can change a patrol, so those are the only candidates.
+fn part2(map: &Map, start_guards: &[Guard]) -> usize {
+    let start_positions: HashSet<Position> = start_guards.iter().map(|g| g.pos).collect();
     let mut works = HashSet::new();
 
-    for (x, y) in map.visited.iter() {
+    for &(x, y) in map.visited_union().difference(&start_positions) {
         let mut test_obstacles = map.obstacles.clone();
-        test_obstacles.insert((*x, *y));
-        let mut m = Map::new(map.x_size, map.y_size, start_pos, &test_obstacles);
-        if m.check_for_loop(map.visited.len() * 2) {
-            works.insert((*x, *y));
+        test_obstacles.insert((x, y));
+        let mut m = Map::new(map.x_size, map.y_size, start_guards, &test_obstacles);
+        if (0..start_guards.len()).any(|i| m.check_for_loop(i)) {
+            works.insert((x, y));
         }
     }
 
     works.len()
 }
 
+// The patrols with an extra obstruction at `pos`, until every guard has left the map or is
+// caught in a loop
+fn render_obstructed(map: &Map, start_guards: &[Guard], pos: Position) -> String {
+    let mut obstacles = map.obstacles.clone();
+    obstacles.insert(pos);
+    let mut m = Map::new(map.x_size, map.y_size, start_guards, &obstacles).recording_paths();
+    for i in 0..start_guards.len() {
+        m.check_for_loop(i);
+    }
 
-#[derive(Debug)]
+    m.render(Some(pos))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Direction {
     North,
     East,
@@ -56,40 +87,102 @@ impl Direction {
             Direction::West => *self = Self::North,
         }
     }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Direction::North => "north",
+            Direction::East => "east",
+            Direction::South => "south",
+            Direction::West => "west",
+        }
+    }
+
+    fn glyph(&self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<',
+        }
+    }
+
+    fn is_vertical(&self) -> bool {
+        matches!(self, Direction::North | Direction::South)
+    }
+
+    fn from_glyph(c: char) -> Option<Self> {
+        match c {
+            '^' => Some(Direction::North),
+            '>' => Some(Direction::East),
+            'v' => Some(Direction::South),
+            '<' => Some(Direction::West),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Guard {
+    pos: Position,
+    dir: Direction,
 }
 
+// Guards patrol independently: they don't block or otherwise notice each other.
 #[derive(Debug)]
 struct Map {
-    guard_pos: Position,
-    guard_dir: Direction,
+    guards: Vec<Guard>,
     x_size: usize,
     y_size: usize,
     obstacles: HashSet<Position>,
-    visited: HashSet<Position>,
+    // Positions visited by each guard, indexed like `guards`
+    visited: Vec<HashSet<Position>>,
+    // Every state each guard has been in, in order. Turning in place counts as a step. Only
+    // kept up with `record_paths`, the maps part 2 checks for loops only need the start.
+    paths: Vec<Vec<Guard>>,
+    record_paths: bool,
+    // Every state each guard has been in, to catch it going around in circles
+    states: Vec<HashSet<Guard>>,
+    looping: Vec<bool>,
 }
 
 impl Map {
-    fn new(x_size: usize, y_size: usize, guard_pos: Position, obstacles: &HashSet<Position>) -> Self {
-        let mut visited = HashSet::new();
-        visited.insert(guard_pos);
+    fn new(x_size: usize, y_size: usize, guards: &[Guard], obstacles: &HashSet<Position>) -> Self {
+        let visited = guards.iter()
+            .map(|guard| HashSet::from([guard.pos]))
+            .collect();
+        let paths = guards.iter()
+            .map(|&guard| vec![guard])
+            .collect();
 
         Self {
-            guard_pos,
-            guard_dir: Direction::North,
+            guards: guards.to_vec(),
             x_size,
             y_size,
             obstacles: obstacles.clone(),
             visited,
+            paths,
+            record_paths: false,
+            states: guards.iter().map(|&guard| HashSet::from([guard])).collect(),
+            looping: vec![false; guards.len()],
         }
     }
 
+    fn recording_paths(mut self) -> Self {
+        self.record_paths = true;
+        self
+    }
+
+    fn in_bounds(&self, pos: Position) -> bool {
+        pos.0 >= 0 && pos.0 < self.x_size as i32 && pos.1 >= 0 && pos.1 < self.y_size as i32
+    }
+
     fn from_file(path: &str) -> std::io::Result<Self> {
         let f = File::open(path)?;
         let reader = BufReader::new(f);
         let lines = reader.lines();
 
         let mut obstacles: HashSet<Position> = HashSet::new();
-        let mut guard_position: Position = (0, 0);
+        let mut guards = Vec::new();
         let mut x_size = 0;
         let mut y_size = 0;
 
@@ -102,50 +195,148 @@ impl Map {
             }
             for (x, c) in line.chars().enumerate() {
                 x_size = max(x_size, x);
-                match c {
-                    '#' => _ = obstacles.insert((x as i32, y as i32)),
-                    '^' => guard_position = (x as i32, y as i32),
-                    _ => continue,
+                if c == '#' {
+                    obstacles.insert((x as i32, y as i32));
+                } else if let Some(dir) = Direction::from_glyph(c) {
+                    guards.push(Guard { pos: (x as i32, y as i32), dir });
                 }
             }
             y_size = max(y_size, y);
         }
 
-        Ok(Self::new(x_size + 1, y_size + 1, guard_position, &obstacles))
+        Ok(Self::new(x_size + 1, y_size + 1, &guards, &obstacles).recording_paths())
+    }
+
+    // Moves every guard one step, returns false once all of them have left the map or are
+    // caught in a loop
+    fn move_guards(&mut self) -> bool {
+        let mut moved = false;
+        for i in 0..self.guards.len() {
+            moved |= self.patrol(i);
+        }
+
+        moved
+    }
+
+    // Moves guard `i` one step unless it has left the map or is already known to be looping.
+    // False if it can't go on, including when this step shows it's in a loop: it's back at a
+    // position it already had, facing the same way.
+    fn patrol(&mut self, i: usize) -> bool {
+        if self.looping[i] || !self.move_guard(i) {
+            return false;
+        }
+        if !self.states[i].insert(self.guards[i]) {
+            self.looping[i] = true;
+            return false;
+        }
+
+        true
     }
 
-    fn move_guard(&mut self) -> bool {
-        let pos_update = match self.guard_dir {
+    fn move_guard(&mut self, i: usize) -> bool {
+        let guard = self.guards[i];
+        let pos_update = match guard.dir {
             Direction::North => (0, -1),
             Direction::East => (1, 0),
             Direction::South => (0, 1),
             Direction::West => (-1, 0),
         };
-        let next_pos = (self.guard_pos.0 + pos_update.0, self.guard_pos.1 + pos_update.1);
-        if next_pos.0 < 0 || next_pos.0 >= self.x_size as i32 || next_pos.1 < 0 || next_pos.1 >= self.y_size as i32 {
+        let next_pos = (guard.pos.0 + pos_update.0, guard.pos.1 + pos_update.1);
+        if !self.in_bounds(next_pos) {
             return false;
         }
+        let guard = &mut self.guards[i];
         if self.obstacles.contains(&next_pos) {
-            self.guard_dir.turn_right();
-            return self.move_guard();
+            guard.dir.turn_right();
+            if self.record_paths {
+                self.paths[i].push(*guard);
+            }
+            return self.move_guard(i);
+        }
+        guard.pos = next_pos;
+        self.visited[i].insert(next_pos);
+        if self.record_paths {
+            self.paths[i].push(*guard);
         }
-        self.visited.insert(next_pos);
-        self.guard_pos = next_pos;
         true
     }
 
-    fn check_for_loop(&mut self, max_iter: usize) -> bool {
-        let mut last_visited = 1;
-        let mut count = 0;
-        while self.move_guard() {
-            count += 1;
-            if self.visited.len() == last_visited && count > max_iter {
-                return true;
+    // Lets guard `i` patrol until it leaves the map or loops, true if it loops
+    fn check_for_loop(&mut self, i: usize) -> bool {
+        while self.patrol(i) {}
+
+        self.looping[i]
+    }
+
+    fn visited_union(&self) -> HashSet<Position> {
+        self.visited.iter().flatten().copied().collect()
+    }
+
+    fn path_csv(&self) -> String {
+        let mut result = String::from("guard,step,x,y,facing\n");
+        for (i, path) in self.paths.iter().enumerate() {
+            for (step, guard) in path.iter().enumerate() {
+                result += &format!("{},{},{},{},{}\n", i, step, guard.pos.0, guard.pos.1, guard.dir.name());
+            }
+        }
+
+        result
+    }
+
+    // One array of steps per guard
+    fn path_json(&self) -> String {
+        let guards: Vec<String> = self.paths.iter()
+            .map(|path| {
+                let steps: Vec<String> = path.iter()
+                    .map(|guard| format!("{{\"x\":{},\"y\":{},\"facing\":\"{}\"}}",
+                                         guard.pos.0, guard.pos.1, guard.dir.name()))
+                    .collect();
+                format!("[{}]", steps.join(","))
+            })
+            .collect();
+
+        format!("[{}]", guards.join(","))
+    }
+
+    // Draws the patrols so far: `|` and `-` where the guards walked vertically or horizontally,
+    // `+` where they did both (turns and crossings), the starting glyph of each guard, and `O`
+    // for the highlighted obstruction if there is one.
+    fn render(&self, highlight: Option<Position>) -> String {
+        let mut vertical = HashSet::new();
+        let mut horizontal = HashSet::new();
+        for guard in self.paths.iter().flatten() {
+            if guard.dir.is_vertical() {
+                vertical.insert(guard.pos);
+            } else {
+                horizontal.insert(guard.pos);
+            }
+        }
+        let starts: Vec<Guard> = self.paths.iter().map(|path| path[0]).collect();
+
+        let mut result = String::new();
+        for y in 0..self.y_size as i32 {
+            for x in 0..self.x_size as i32 {
+                let pos = (x, y);
+                let c = if highlight == Some(pos) {
+                    'O'
+                } else if self.obstacles.contains(&pos) {
+                    '#'
+                } else if let Some(start) = starts.iter().find(|g| g.pos == pos) {
+                    start.dir.glyph()
+                } else {
+                    match (vertical.contains(&pos), horizontal.contains(&pos)) {
+                        (true, true) => '+',
+                        (true, false) => '|',
+                        (false, true) => '-',
+                        (false, false) => '.',
+                    }
+                };
+                result.push(c);
             }
-            last_visited = self.visited.len();
+            result.push('\n');
         }
 
-        false
+        result
     }
 }
 
@@ -164,9 +355,119 @@ mod day06_tests {
     #[test]
     fn test_part2() -> std::io::Result<()> {
         let mut map = Map::from_file("../test_input/day06test.txt")?;
-        let init_guard_pos = map.guard_pos;
+        let init_guards = map.guards.clone();
         part1(&mut map);
-        assert_eq!(part2(&mut map, init_guard_pos), 6);
+        assert_eq!(part2(&map, &init_guards), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_guard_glyphs() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day06test_guards.txt")?;
+        assert_eq!(map.guards, vec![
+            Guard { pos: (2, 0), dir: Direction::South },
+            Guard { pos: (0, 4), dir: Direction::East },
+            Guard { pos: (4, 6), dir: Direction::North },
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_guards() -> std::io::Result<()> {
+        let mut map = Map::from_file("../test_input/day06test_guards.txt")?;
+        let init_guards = map.guards.clone();
+        let union = part1(&mut map);
+        let per_guard: Vec<usize> = map.visited.iter().map(|v| v.len()).collect();
+        assert_eq!(per_guard, vec![5, 24, 41]);
+        assert_eq!(union, 48);
+        // Guards trapped by the same obstruction only count it once
+        assert_eq!(part2(&map, &init_guards), 6);
+
+        let mut east_only = Map::new(map.x_size, map.y_size, &init_guards[1..2], &map.obstacles);
+        part1(&mut east_only);
+        assert_eq!(part2(&east_only, &init_guards[1..2]), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_looping_guard() -> std::io::Result<()> {
+        // Guard 0 is boxed in from the start, guard 1 walks off the map
+        let mut map = Map::from_file("../test_input/day06test_looping.txt")?;
+        let init_guards = map.guards.clone();
+        assert_eq!(part1(&mut map), 9);
+        let per_guard: Vec<usize> = map.visited.iter().map(|v| v.len()).collect();
+        assert_eq!(per_guard, vec![4, 5]);
+        assert_eq!(map.looping, vec![true, false]);
+        // Anything on guard 1's way keeps guard 0 trapped, anything on guard 0's loop frees it
+        assert_eq!(part2(&map, &init_guards), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_export() -> std::io::Result<()> {
+        let mut map = Map::from_file("../test_input/day06test.txt")?;
+        part1(&mut map);
+        let csv = map.path_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("guard,step,x,y,facing"));
+        assert_eq!(lines.next(), Some("0,0,4,6,north"));
+        assert_eq!(lines.next(), Some("0,1,4,5,north"));
+        // The first turn happens in place below the obstruction at (4, 0)
+        assert_eq!(lines.nth(3), Some("0,5,4,1,north"));
+        assert_eq!(lines.next(), Some("0,6,4,1,east"));
+        assert!(map.path_json().starts_with(r#"[[{"x":4,"y":6,"facing":"north"},{"x":4,"y":5,"facing":"north"}"#));
+
+        // Maps that only check for loops don't keep the path
+        let mut obstacles = map.obstacles.clone();
+        obstacles.insert((3, 6));
+        let start = Map::from_file("../test_input/day06test.txt")?.guards;
+        let mut m = Map::new(map.x_size, map.y_size, &start, &obstacles);
+        assert!(m.check_for_loop(0));
+        assert_eq!(m.paths, vec![start.clone()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_loops() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day06test.txt")?;
+        let render_with = |pos: Position| {
+            let mut obstacles = map.obstacles.clone();
+            obstacles.insert(pos);
+            let mut m = Map::new(map.x_size, map.y_size, &map.guards, &obstacles).recording_paths();
+            assert!(m.check_for_loop(0));
+            m.render(Some(pos))
+        };
+        assert_eq!(render_obstructed(&map, &map.guards, (3, 6)), render_with((3, 6)));
+
+        assert_eq!(render_with((3, 6)), "\
+....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#...
+");
+        assert_eq!(render_with((6, 7)), "\
+....#.....
+....+---+#
+....|...|.
+..#.|...|.
+..+-+-+#|.
+..|.|.|.|.
+.#+-^-+-+.
+......O.#.
+#.........
+......#...
+");
 
         Ok(())
     }
@@ -1,31 +1,134 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-struct Equation(u64, Vec<u64>);
+struct Equation<T>(T, Vec<T>);
+
+// The integer types equations can be solved over. All arithmetic is checked so that branches
+// which would overflow are pruned instead of wrapping or panicking.
+trait Value: Copy + Ord + std::fmt::Display + std::str::FromStr {
+    const ZERO: Self;
+    const TEN: Self;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn checked_div(self, rhs: Self) -> Option<Self>;
+    fn is_multiple_of(self, rhs: Self) -> bool;
+    fn xor(self, rhs: Self) -> Self;
+}
+
+macro_rules! impl_value {
+    ($($t:ty),*) => {$(
+        impl Value for $t {
+            const ZERO: Self = 0;
+            const TEN: Self = 10;
+
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_add(self, rhs)
+            }
+
+            fn checked_sub(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_sub(self, rhs)
+            }
+
+            fn checked_mul(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_mul(self, rhs)
+            }
+
+            fn checked_div(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_div(self, rhs)
+            }
+
+            fn is_multiple_of(self, rhs: Self) -> bool {
+                <$t>::is_multiple_of(self, rhs)
+            }
+
+            fn xor(self, rhs: Self) -> Self {
+                self ^ rhs
+            }
+        }
+    )*};
+}
+
+impl_value!(u64, u128);
 
 fn main() -> std::io::Result<()> {
-    let equations = parse_equations("input/day07.txt")?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // --wide solves over u128 for inputs with values beyond u64
+    let wide = args.iter().any(|arg| arg == "--wide");
+    // --precedence evaluates with the usual operator precedence instead of left to right
+    let mode = if args.iter().any(|arg| arg == "--precedence") {
+        Evaluation::Precedence
+    } else {
+        Evaluation::LeftToRight
+    };
+    let symbols = args.iter().find(|arg| !arg.starts_with("--")).map(String::as_str);
 
+    if wide {
+        run(&parse_equations::<u128>("input/day07.txt")?, symbols, mode);
+    } else {
+        run(&parse_equations::<u64>("input/day07.txt")?, symbols, mode);
+    }
+
+    Ok(())
+}
+
+fn run<T: Value>(equations: &[Equation<T>], symbols: Option<&str>, mode: Evaluation) {
     let part1_start = std::time::Instant::now();
-    let part1 = equations.iter().filter(|&e| is_valid(e, false))
-        .map(|e| e.0).sum::<u64>();
+    let part1 = display_sum(calibration_result(equations, &Solver::part1().with_mode(mode)));
     let part2_start = std::time::Instant::now();
-    let part2 = equations.iter().filter(|&e| is_valid(e, true))
-        .map(|e| e.0).sum::<u64>();
+    let part2 = display_sum(calibration_result(equations, &Solver::part2().with_mode(mode)));
     let end = std::time::Instant::now();
     println!("Part1 = {}, duration: {:?}", part1, part2_start.duration_since(part1_start));
     println!("Part2 = {}, duration: {:?}", part2, end.duration_since(part2_start));
     println!("Total: {:?}", end.duration_since(part1_start));
 
-    Ok(())
+    if let Some(symbols) = symbols {
+        let Some(solver) = Solver::from_symbols(symbols) else {
+            eprintln!("Unknown operator in {:?}", symbols);
+            return;
+        };
+        let solver = solver.with_mode(mode);
+        println!("With {}: {}", symbols, display_sum(calibration_result(equations, &solver)));
+        for e in equations.iter() {
+            let Some(witness) = solver.witness(e) else {
+                continue;
+            };
+            println!("  {}", witness);
+            let count = solver.count_solutions(e);
+            if count > 1 {
+                println!("    ambiguous, {} solutions:", count);
+                for ops in solver.all_solutions(e) {
+                    println!("    {}", solver.render(e, &ops));
+                }
+            }
+        }
+    }
+}
+
+fn display_sum<T: Value>(sum: Option<T>) -> String {
+    match sum {
+        Some(sum) => sum.to_string(),
+        None => "overflow (try --wide)".to_string(),
+    }
 }
 
-fn parse_equations(path: &str) -> std::io::Result<Vec<Equation>> {
+// None if the sum itself overflows
+fn calibration_result<T: Value>(equations: &[Equation<T>], solver: &Solver<T>) -> Option<T> {
+    equations.iter().filter(|&e| solver.is_valid(e))
+        .try_fold(T::ZERO, |sum, e| sum.checked_add(e.0))
+}
+
+fn parse_equations<T: Value>(path: &str) -> std::io::Result<Vec<Equation<T>>> {
     let f = File::open(path)?;
     let reader = BufReader::new(f);
     let lines = reader.lines();
 
     let mut equations = Vec::new();
+    let parse = |s: &str| s.parse::<T>().map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData,
+                            format!("{:?} is not a number or doesn't fit", s))
+    });
 
     for line in lines {
         let Ok(line) = line else {
@@ -36,40 +139,406 @@ fn parse_equations(path: &str) -> std::io::Result<Vec<Equation>> {
         }
         let line = line.trim();
         let parts = line.split_whitespace().collect::<Vec<_>>();
-        let test_val = parts[0][0..parts[0].len() - 1].parse::<u64>().unwrap();
-        let numbers = parts[1..].iter().map(|s| s.parse::<u64>().unwrap()).collect::<Vec<_>>();
+        let test_val = parse(&parts[0][0..parts[0].len() - 1])?;
+        let numbers = parts[1..].iter().map(|s| parse(s)).collect::<std::io::Result<Vec<_>>>()?;
         equations.push(Equation(test_val, numbers));
     }
 
     Ok(equations)
 }
 
-fn is_valid(equation: &Equation, part2: bool) -> bool {
-    match equation.1.as_slice() {
-        [num] => *num == equation.0,
-        [num1, num2] => {
-            equation.0 == num1 + num2
-                || equation.0 == num1 * num2
-                || (part2 && equation.0 == concat(*num1, *num2))
-        },
-        [num1, num2, rest @ ..] => {
-            let mut vec1 = vec![num1 + num2];
-            let mut vec2 = vec![num1 * num2];
-            let mut vec3 = vec![concat(*num1, *num2)];
-            vec1.extend(rest);
-            vec2.extend(rest);
-            vec3.extend(rest);
+// What an operator knows about the left operand, given its result and right operand
+enum Inverse<T> {
+    // Exactly one left operand gives the result
+    Unique(T),
+    // No left operand gives the result
+    Impossible,
+    // The operator can't tell (several or unbounded left operands might work)
+    Unknown,
+}
+
+trait Operator<T: Value> {
+    fn symbol(&self) -> &'static str;
+
+    // None when the operation is undefined for these operands or overflows
+    fn apply(&self, lhs: T, rhs: T) -> Option<T>;
+
+    fn invert(&self, _result: T, _rhs: T) -> Inverse<T> {
+        Inverse::Unknown
+    }
+
+    // How tightly the operator binds in `Evaluation::Precedence`, higher binds tighter
+    fn precedence(&self) -> u8 {
+        1
+    }
+}
+
+struct Add;
+struct Mul;
+struct Concat;
+struct Sub;
+struct Xor;
+struct Max;
+
+impl<T: Value> Operator<T> for Add {
+    fn symbol(&self) -> &'static str {
+        "+"
+    }
+
+    fn apply(&self, lhs: T, rhs: T) -> Option<T> {
+        lhs.checked_add(rhs)
+    }
+
+    fn invert(&self, result: T, rhs: T) -> Inverse<T> {
+        match result.checked_sub(rhs) {
+            Some(lhs) => Inverse::Unique(lhs),
+            None => Inverse::Impossible,
+        }
+    }
+}
+
+impl<T: Value> Operator<T> for Mul {
+    fn symbol(&self) -> &'static str {
+        "*"
+    }
+
+    fn apply(&self, lhs: T, rhs: T) -> Option<T> {
+        lhs.checked_mul(rhs)
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+
+    fn invert(&self, result: T, rhs: T) -> Inverse<T> {
+        if rhs == T::ZERO {
+            // Anything times zero is zero
+            return if result == T::ZERO { Inverse::Unknown } else { Inverse::Impossible };
+        }
+        match result.checked_div(rhs) {
+            Some(lhs) if result.is_multiple_of(rhs) => Inverse::Unique(lhs),
+            _ => Inverse::Impossible,
+        }
+    }
+}
+
+impl<T: Value> Operator<T> for Concat {
+    fn symbol(&self) -> &'static str {
+        "||"
+    }
+
+    fn apply(&self, lhs: T, rhs: T) -> Option<T> {
+        concat(lhs, rhs)
+    }
+
+    // Concatenation glues digits together into a single number before anything else happens
+    fn precedence(&self) -> u8 {
+        3
+    }
+
+    // Strips the digits of `rhs` off the end of `result`
+    fn invert(&self, result: T, rhs: T) -> Inverse<T> {
+        let Some(diff) = result.checked_sub(rhs) else {
+            return Inverse::Impossible;
+        };
+        match digit_shift(rhs) {
+            Some(shift) if diff.is_multiple_of(shift) => match diff.checked_div(shift) {
+                Some(lhs) => Inverse::Unique(lhs),
+                None => Inverse::Impossible,
+            },
+            // Only a zero can go in front of a number with as many digits as the type allows
+            None if diff == T::ZERO => Inverse::Unique(T::ZERO),
+            _ => Inverse::Impossible,
+        }
+    }
+}
+
+impl<T: Value> Operator<T> for Sub {
+    fn symbol(&self) -> &'static str {
+        "-"
+    }
+
+    fn apply(&self, lhs: T, rhs: T) -> Option<T> {
+        lhs.checked_sub(rhs)
+    }
+
+    fn invert(&self, result: T, rhs: T) -> Inverse<T> {
+        match result.checked_add(rhs) {
+            Some(lhs) => Inverse::Unique(lhs),
+            None => Inverse::Impossible,
+        }
+    }
+}
+
+impl<T: Value> Operator<T> for Xor {
+    fn symbol(&self) -> &'static str {
+        "^"
+    }
+
+    fn apply(&self, lhs: T, rhs: T) -> Option<T> {
+        Some(lhs.xor(rhs))
+    }
+
+    // Like in most programming languages, below the arithmetic operators
+    fn precedence(&self) -> u8 {
+        0
+    }
+
+    fn invert(&self, result: T, rhs: T) -> Inverse<T> {
+        Inverse::Unique(result.xor(rhs))
+    }
+}
+
+impl<T: Value> Operator<T> for Max {
+    fn symbol(&self) -> &'static str {
+        "max"
+    }
+
+    fn apply(&self, lhs: T, rhs: T) -> Option<T> {
+        Some(lhs.max(rhs))
+    }
+
+    fn invert(&self, result: T, rhs: T) -> Inverse<T> {
+        match rhs.cmp(&result) {
+            std::cmp::Ordering::Less => Inverse::Unique(result),
+            std::cmp::Ordering::Equal => Inverse::Unknown,
+            std::cmp::Ordering::Greater => Inverse::Impossible,
+        }
+    }
+}
+
+fn operator_from_symbol<T: Value>(symbol: &str) -> Option<Box<dyn Operator<T>>> {
+    match symbol {
+        "+" => Some(Box::new(Add)),
+        "*" => Some(Box::new(Mul)),
+        "||" => Some(Box::new(Concat)),
+        "-" => Some(Box::new(Sub)),
+        "^" => Some(Box::new(Xor)),
+        "max" => Some(Box::new(Max)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Evaluation {
+    // Strictly left to right, like the puzzle
+    LeftToRight,
+    // Higher precedence operators first, left to right among equal ones
+    Precedence,
+}
+
+struct Solver<T: Value> {
+    operators: Vec<Box<dyn Operator<T>>>,
+    mode: Evaluation,
+}
+
+impl<T: Value> Solver<T> {
+    fn new(operators: Vec<Box<dyn Operator<T>>>) -> Self {
+        Self { operators, mode: Evaluation::LeftToRight }
+    }
+
+    fn with_mode(mut self, mode: Evaluation) -> Self {
+        self.mode = mode;
+        self
+    }
 
-            is_valid(&Equation(equation.0, vec1), part2)
-                || is_valid(&Equation(equation.0, vec2), part2)
-                || (part2 && is_valid(&Equation(equation.0, vec3), part2))
-        },
-        _ => false,
+    fn part1() -> Self {
+        Self::new(vec![Box::new(Add), Box::new(Mul)])
+    }
+
+    fn part2() -> Self {
+        Self::new(vec![Box::new(Add), Box::new(Mul), Box::new(Concat)])
+    }
+
+    // Comma separated operator symbols, e.g. "+,*,||,max"
+    fn from_symbols(symbols: &str) -> Option<Self> {
+        let operators = symbols.split(',')
+            .map(|symbol| operator_from_symbol(symbol.trim()))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self::new(operators))
+    }
+
+    fn is_valid(&self, equation: &Equation<T>) -> bool {
+        self.search(equation, &mut |_| true)
+    }
+
+    // The operators (as indices into `self.operators`, left to right) of one assignment that
+    // makes the equation true
+    fn solve(&self, equation: &Equation<T>) -> Option<Vec<usize>> {
+        let mut witness = None;
+        self.search(equation, &mut |ops| {
+            witness = Some(ops.to_vec());
+            true
+        });
+
+        witness
+    }
+
+    fn all_solutions(&self, equation: &Equation<T>) -> Vec<Vec<usize>> {
+        let mut solutions = Vec::new();
+        self.search(equation, &mut |ops| {
+            solutions.push(ops.to_vec());
+            false
+        });
+
+        solutions
+    }
+
+    fn count_solutions(&self, equation: &Equation<T>) -> usize {
+        let mut count = 0;
+        self.search(equation, &mut |_| {
+            count += 1;
+            false
+        });
+
+        count
+    }
+
+    // Renders a solution like "190 = 10 * 19"
+    fn render(&self, equation: &Equation<T>, ops: &[usize]) -> String {
+        let mut result = format!("{} = {}", equation.0, equation.1[0]);
+        for (op, num) in ops.iter().zip(equation.1[1..].iter()) {
+            result += &format!(" {} {}", self.operators[*op].symbol(), num);
+        }
+
+        result
+    }
+
+    fn witness(&self, equation: &Equation<T>) -> Option<String> {
+        self.solve(equation).map(|ops| self.render(equation, &ops))
+    }
+
+    // Calls `visit` with every operator assignment that makes the equation true, until it
+    // returns true. Each assignment is visited at most once.
+    fn search(&self, equation: &Equation<T>, visit: &mut dyn FnMut(&[usize]) -> bool) -> bool {
+        match self.mode {
+            Evaluation::LeftToRight => {
+                self.search_backward(&equation.1, equation.0, &mut Vec::new(), visit)
+            },
+            Evaluation::Precedence => {
+                let Some((&first, rest)) = equation.1.split_first() else {
+                    return false;
+                };
+                let mut check = |value, ops: &[usize]| value == equation.0 && visit(ops);
+                self.search_precedence(rest, vec![first], Vec::new(), &mut Vec::new(), &mut check)
+            },
+        }
+    }
+
+    // With precedence a prefix doesn't have a value of its own (the next operator might bind
+    // tighter than the last one), so nothing can be undone from the back. Instead this walks
+    // forward like a shunting-yard evaluator: `values` and `pending` hold the operands and
+    // operators still waiting for something of lower precedence. They never get deeper than
+    // the number of precedence levels, so copying them per branch is cheap.
+    fn search_precedence(&self, numbers: &[T], values: Vec<T>, pending: Vec<usize>,
+                         chosen: &mut Vec<usize>, check: &mut dyn FnMut(T, &[usize]) -> bool) -> bool {
+        match numbers {
+            [] => {
+                let (mut values, mut pending) = (values, pending);
+                match self.reduce(&mut values, &mut pending, 0) {
+                    Some(()) => check(values[0], chosen),
+                    None => false,
+                }
+            },
+            [num, rest @ ..] => (0..self.operators.len()).any(|i| {
+                let (mut values, mut pending) = (values.clone(), pending.clone());
+                let precedence = self.operators[i].precedence();
+                if self.reduce(&mut values, &mut pending, precedence).is_none() {
+                    return false;
+                }
+                values.push(*num);
+                pending.push(i);
+                chosen.push(i);
+                let found = self.search_precedence(rest, values, pending, chosen, check);
+                chosen.pop();
+                found
+            }),
+        }
+    }
+
+    // Applies pending operators that bind at least as tightly as `min_precedence`. None if one
+    // of them overflows or is undefined.
+    fn reduce(&self, values: &mut Vec<T>, pending: &mut Vec<usize>, min_precedence: u8) -> Option<()> {
+        while let Some(&top) = pending.last() {
+            if self.operators[top].precedence() < min_precedence {
+                break;
+            }
+            pending.pop();
+            let rhs = values.pop()?;
+            let lhs = values.pop()?;
+            values.push(self.operators[top].apply(lhs, rhs)?);
+        }
+
+        Some(())
+    }
+
+    // Peels numbers off the end, undoing each operator to get the value the rest of the
+    // equation has to produce. Most branches die right away (a sum going negative, a division
+    // with a remainder, digits that don't match). Where an operator can't be undone the
+    // remaining prefix gets evaluated forward instead. `suffix` holds the operators chosen so
+    // far, last one first.
+    fn search_backward(&self, numbers: &[T], target: T, suffix: &mut Vec<usize>,
+                       visit: &mut dyn FnMut(&[usize]) -> bool) -> bool {
+        match numbers {
+            [] => false,
+            [num] => *num == target && visit(&suffix.iter().rev().copied().collect::<Vec<_>>()),
+            [rest @ .., last] => (0..self.operators.len()).any(|i| {
+                let op = &self.operators[i];
+                suffix.push(i);
+                let found = match op.invert(target, *last) {
+                    Inverse::Unique(lhs) => self.search_backward(rest, lhs, suffix, visit),
+                    Inverse::Impossible => false,
+                    Inverse::Unknown => {
+                        self.search_forward(rest[0], &rest[1..], &mut Vec::new(), &mut |value, prefix| {
+                            op.apply(value, *last) == Some(target)
+                                && visit(&[prefix, &suffix.iter().rev().copied().collect::<Vec<_>>()].concat())
+                        })
+                    },
+                };
+                suffix.pop();
+                found
+            }),
+        }
+    }
+
+    // Evaluates every operator assignment left to right, stops as soon as `check` accepts a
+    // result. `prefix` holds the operators chosen so far.
+    fn search_forward(&self, acc: T, numbers: &[T], prefix: &mut Vec<usize>,
+                      check: &mut dyn FnMut(T, &[usize]) -> bool) -> bool {
+        match numbers {
+            [] => check(acc, prefix),
+            [num, rest @ ..] => (0..self.operators.len()).any(|i| {
+                let Some(value) = self.operators[i].apply(acc, *num) else {
+                    return false;
+                };
+                prefix.push(i);
+                let found = self.search_forward(value, rest, prefix, check);
+                prefix.pop();
+                found
+            }),
+        }
+    }
+}
+
+// None on overflow
+fn concat<T: Value>(num1: T, num2: T) -> Option<T> {
+    match digit_shift(num2) {
+        Some(shift) => num1.checked_mul(shift)?.checked_add(num2),
+        None if num1 == T::ZERO => Some(num2),
+        None => None,
     }
 }
 
-fn concat(num1: u64, num2: u64) -> u64 {
-    num1 * 10_u64.pow(num2.to_string().len() as u32) + num2
+// The power of ten that shifts a number left by as many digits as `num` has, None if that
+// doesn't fit in `T`
+fn digit_shift<T: Value>(num: T) -> Option<T> {
+    let mut shift = T::TEN;
+    while shift <= num {
+        shift = shift.checked_mul(T::TEN)?;
+    }
+
+    Some(shift)
 }
 
 #[cfg(test)]
@@ -78,26 +547,180 @@ mod tests {
 
     #[test]
     fn test_concat() {
-        assert_eq!(concat(11, 12), 1112);
-        assert_eq!(concat(123, 45), 12345);
+        assert_eq!(concat(11_u64, 12), Some(1112));
+        assert_eq!(concat(123_u64, 45), Some(12345));
     }
 
     #[test]
     fn test_part1() -> std::io::Result<()> {
-        let equations = parse_equations("../test_input/day07test.txt")?;
-        let part1 = equations.iter().filter(|&e| is_valid(e, false))
-            .map(|e| e.0).sum::<u64>();
-        assert_eq!(part1, 3749);
+        let equations = parse_equations::<u64>("../test_input/day07test.txt")?;
+        let part1 = calibration_result(&equations, &Solver::part1());
+        assert_eq!(part1, Some(3749));
 
         Ok(())
     }
 
     #[test]
     fn test_part2() -> std::io::Result<()> {
-        let equations = parse_equations("../test_input/day07test.txt")?;
-        let part2 = equations.iter().filter(|&e| is_valid(e, true))
-            .map(|e| e.0).sum::<u64>();
-        assert_eq!(part2, 11387);
+        let equations = parse_equations::<u64>("../test_input/day07test.txt")?;
+        let part2 = calibration_result(&equations, &Solver::part2());
+        assert_eq!(part2, Some(11387));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backward_matches_forward() -> std::io::Result<()> {
+        let equations = parse_equations::<u64>("../test_input/day07test.txt")?;
+        for symbols in ["+,*", "+,*,||", "-,^", "+,max", "*,-,max"] {
+            let solver = Solver::from_symbols(symbols).unwrap();
+            for e in equations.iter() {
+                let mut forward = 0;
+                solver.search_forward(e.1[0], &e.1[1..], &mut Vec::new(), &mut |value, _| {
+                    forward += (value == e.0) as usize;
+                    false
+                });
+                assert_eq!(solver.count_solutions(e), forward, "{} with {}", e.0, symbols);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_operators() {
+        let solver = Solver::<u64>::from_symbols("-,^,max").unwrap();
+        assert!(solver.is_valid(&Equation(5, vec![10, 3, 2])));
+        assert!(solver.is_valid(&Equation(3, vec![1, 2])));
+        assert!(solver.is_valid(&Equation(19, vec![4, 5, 19])));
+        assert!(!solver.is_valid(&Equation(20, vec![4, 5, 19])));
+        assert!(Solver::<u64>::from_symbols("+,/").is_none());
+    }
+
+    #[test]
+    fn test_multiply_by_zero() {
+        assert!(Solver::<u64>::part1().is_valid(&Equation(0, vec![3, 4, 0])));
+        assert!(!Solver::<u64>::part1().is_valid(&Equation(8, vec![3, 4, 0])));
+    }
+
+    #[test]
+    fn test_no_operands() {
+        for mode in [Evaluation::LeftToRight, Evaluation::Precedence] {
+            assert!(!Solver::<u64>::part2().with_mode(mode).is_valid(&Equation(5, vec![])));
+            assert_eq!(Solver::<u64>::part2().with_mode(mode).count_solutions(&Equation(0, vec![])), 0);
+        }
+    }
+
+    #[test]
+    fn test_witness() -> std::io::Result<()> {
+        let equations = parse_equations::<u64>("../test_input/day07test.txt")?;
+        let solver = Solver::part2();
+        let witnesses: Vec<Option<String>> = equations.iter().map(|e| solver.witness(e)).collect();
+        assert_eq!(witnesses, vec![
+            Some("190 = 10 * 19".to_string()),
+            Some("3267 = 81 * 40 + 27".to_string()),
+            None,
+            Some("156 = 15 || 6".to_string()),
+            Some("7290 = 6 * 8 || 6 * 15".to_string()),
+            None,
+            Some("192 = 17 || 8 + 14".to_string()),
+            None,
+            Some("292 = 11 + 6 * 16 + 20".to_string()),
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ambiguous_solutions() -> std::io::Result<()> {
+        let equations = parse_equations::<u64>("../test_input/day07test.txt")?;
+        let solver = Solver::part1();
+        let counts: Vec<usize> = equations.iter().map(|e| solver.count_solutions(e)).collect();
+        assert_eq!(counts, vec![1, 2, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(solver.all_solutions(&equations[1]).len(), 2);
+        for ops in solver.all_solutions(&equations[1]) {
+            assert!(["3267 = 81 + 40 * 27", "3267 = 81 * 40 + 27"]
+                .contains(&solver.render(&equations[1], &ops).as_str()));
+        }
+        assert_eq!(Solver::<u64>::part1().count_solutions(&Equation(4, vec![2, 2])), 2);
+        assert_eq!(Solver::<u64>::part1().count_solutions(&Equation(0, vec![5, 0, 0])), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_is_pruned() {
+        let max = u64::MAX;
+        let solver = Solver::<u64>::part2();
+        assert!(solver.is_valid(&Equation(max, vec![max - 1, 1])));
+        assert!(!solver.is_valid(&Equation(3, vec![max, 2])));
+        // 18446744073709551615 is u64::MAX, one more digit doesn't fit
+        assert!(solver.is_valid(&Equation(max, vec![max / 10, 5])));
+        assert_eq!(concat(max / 10, 6), None);
+        assert_eq!(concat(0, max), Some(max));
+        assert_eq!(concat(1, max), None);
+        assert!(solver.is_valid(&Equation(max, vec![0, max])));
+
+        // The forward fallback of `max` prunes too
+        let solver = Solver::<u64>::from_symbols("+,max").unwrap();
+        assert_eq!(solver.count_solutions(&Equation(max, vec![max, 1, max])), 1);
+    }
+
+    #[test]
+    fn test_wide_mode() -> std::io::Result<()> {
+        assert!(parse_equations::<u64>("../test_input/day07test_wide.txt").is_err());
+        let equations = parse_equations::<u128>("../test_input/day07test_wide.txt")?;
+        let witnesses: Vec<Option<String>> = equations.iter()
+            .map(|e| Solver::part2().witness(e))
+            .collect();
+        assert_eq!(witnesses, vec![
+            Some("36893488147419103230 = 18446744073709551615 * 2".to_string()),
+            Some("184467440737095516152 = 18446744073709551615 || 0 * 1 + 1 + 1".to_string()),
+            None,
+        ]);
+        assert_eq!(calibration_result(&equations, &Solver::part1()), Some(36893488147419103230));
+
+        let max = u128::MAX;
+        assert!(!Solver::part2().is_valid(&Equation(3, vec![max, 2])));
+        assert!(Solver::part2().is_valid(&Equation(max, vec![max / 10, 5])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_precedence() -> std::io::Result<()> {
+        let equations = parse_equations::<u64>("../test_input/day07test.txt")?;
+        let statuses = |solver: Solver<u64>| -> Vec<bool> {
+            equations.iter().map(|e| solver.is_valid(e)).collect()
+        };
+
+        // 3267 = 81 * 40 + 27 still works, 292 = 11 + 6 * 16 + 20 doesn't anymore
+        assert_eq!(statuses(Solver::part1()),
+                   vec![true, true, false, false, false, false, false, false, true]);
+        assert_eq!(statuses(Solver::part1().with_mode(Evaluation::Precedence)),
+                   vec![true, true, false, false, false, false, false, false, false]);
+        // 7290 = 6 * 8 || 6 * 15 becomes 6 * 86 * 15, 192 = 17 || 8 + 14 is unaffected
+        assert_eq!(statuses(Solver::part2()),
+                   vec![true, true, false, true, true, false, true, false, true]);
+        assert_eq!(statuses(Solver::part2().with_mode(Evaluation::Precedence)),
+                   vec![true, true, false, true, false, false, true, false, false]);
+
+        let solver = Solver::<u64>::from_symbols("+,||").unwrap().with_mode(Evaluation::Precedence);
+        assert_eq!(solver.witness(&Equation(24, vec![1, 2, 3])), Some("24 = 1 + 2 || 3".to_string()));
+        assert!(!Solver::<u64>::from_symbols("+,||").unwrap().is_valid(&Equation(24, vec![1, 2, 3])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_precedence_single_level() -> std::io::Result<()> {
+        // Operators on one precedence level evaluate left to right either way
+        let equations = parse_equations::<u64>("../test_input/day07test.txt")?;
+        let left_to_right = Solver::<u64>::from_symbols("+,-,max").unwrap();
+        let precedence = Solver::<u64>::from_symbols("+,-,max").unwrap().with_mode(Evaluation::Precedence);
+        for e in equations.iter() {
+            assert_eq!(left_to_right.count_solutions(e), precedence.count_solutions(e));
+        }
 
         Ok(())
     }
@@ -4,12 +4,21 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use itertools::Itertools;
 
-#[derive(Eq, Hash, PartialEq, Debug)]
-struct Position(i32, i32);
+// A point in N-dimensional space, x first
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+struct Position<const N: usize>([i32; N]);
 
-impl Position {
-    pub(crate) fn clone(&self) -> Self {
-        Position(self.0, self.1)
+impl<const N: usize> Position<N> {
+    fn diff(&self, other: &Self) -> [i32; N] {
+        std::array::from_fn(|i| other.0[i] - self.0[i])
+    }
+
+    fn add(&self, diff: &[i32; N]) -> Self {
+        Position(std::array::from_fn(|i| self.0[i] + diff[i]))
+    }
+
+    fn sub(&self, diff: &[i32; N]) -> Self {
+        Position(std::array::from_fn(|i| self.0[i] - diff[i]))
     }
 }
 
@@ -17,83 +26,178 @@ fn main() -> std::io::Result<()> {
     let map = Map::from_file("input/day08.txt")?;
 
     println!("Part1: {}", part1(&map));
-    println!("Part1: {}", part2(&map));
+    println!("Part2: {}", part2(&map, HarmonicStep::Offset));
+    println!("Part2 (every grid point in line): {}", part2(&map, HarmonicStep::Reduced));
+
+    for rule in [Rule::Pairwise, Rule::Harmonic(HarmonicStep::Offset)] {
+        for goal in [Goal::Maximise, Goal::Minimise] {
+            for frequency in map.antennas.keys().sorted() {
+                let Some(placement) = best_placement(&map, *frequency, rule, goal) else {
+                    continue;
+                };
+                println!("{:?} {:?} with {}: {} antinodes at {:?} ({} positions tie)", goal, rule,
+                         frequency, placement.antinodes, placement.positions[0], placement.positions.len());
+            }
+        }
+    }
+
+    // Optionally a 3D map as 2D layers separated by blank lines
+    if let Some(path) = std::env::args().nth(1) {
+        let volume = Map::from_layers_file(&path)?;
+        println!("3D part1: {}", part1(&volume));
+        println!("3D part2: {}", part2(&volume, HarmonicStep::Offset));
+    }
 
     Ok(())
 }
 
 
 // Count antinodes for each frequency
-fn part1(map: &Map) -> usize {
+fn part1<const N: usize>(map: &Map<N>) -> usize {
+    antinodes(map, Rule::Pairwise).len()
+}
+
+fn part2<const N: usize>(map: &Map<N>, step: HarmonicStep) -> usize {
+    antinodes(map, Rule::Harmonic(step)).len()
+}
+
+// Which antinodes a pair of antennas produces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    // One on either side, as far out as the antennas are apart (part1)
+    Pairwise,
+    // All along the line through both antennas (part2)
+    Harmonic(HarmonicStep),
+}
+
+fn antinodes<const N: usize>(map: &Map<N>, rule: Rule) -> HashSet<Position<N>> {
     let mut result = HashSet::new();
     // Check each pair of each frequency and add their antinodes
     for v in map.antennas.values() {
         for pair in v.iter().combinations(2) {
-            let (antinode0, antinode1) = find_antinodes(pair[0], pair[1]);
-            if map.in_map(&antinode0) {
-                result.insert(antinode0);
-            }
-            if map.in_map(&antinode1) {
-                result.insert(antinode1);
-            }
+            result.extend(pair_antinodes(pair[0], pair[1], map, rule));
         }
     }
 
-    result.len()
+    result
 }
 
-fn part2(map: &Map) -> usize {
-    let mut result = HashSet::new();
-    for v in map.antennas.values() {
-        for pair in v.iter().combinations(2) {
-            for antinode in find_harmonic_antinodes(pair[0], pair[1], map) {
-                result.insert(antinode);
-            }
-        }
+// The antinodes of a pair of antennas that are on the map
+fn pair_antinodes<const N: usize>(p0: &Position<N>, p1: &Position<N>, map: &Map<N>,
+                                  rule: Rule) -> Vec<Position<N>> {
+    match rule {
+        Rule::Pairwise => {
+            let (antinode0, antinode1) = find_antinodes(p0, p1);
+            [antinode0, antinode1].into_iter().filter(|p| map.in_map(p)).collect()
+        },
+        Rule::Harmonic(step) => find_harmonic_antinodes(p0, p1, map, step),
     }
+}
 
-    result.len()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Goal {
+    Maximise,
+    Minimise,
 }
 
-fn find_antinodes(p0: &Position, p1: &Position) -> (Position, Position) {
-    let diff = (p1.0 - p0.0, p1.1 - p0.1);
-    (Position(p0.0 - diff.0, p0.1 - diff.1), Position(p1.0 + diff.0, p1.1 + diff.1))
+#[derive(Debug, PartialEq)]
+struct Placement<const N: usize> {
+    // Every free position that reaches the optimum, in reading order
+    positions: Vec<Position<N>>,
+    // Unique antinode locations over all frequencies with the new antenna in place
+    antinodes: usize,
 }
 
-fn find_harmonic_antinodes(p0: &Position, p1: &Position, map: &Map) -> Vec<Position> {
-    let mut result = vec![p0.clone(), p1.clone()];
-    let diff = (p1.0 - p0.0, p1.1 - p0.1);
+// Finds where a new antenna of `frequency` gives the most (or fewest) unique antinode locations.
+// A new antenna only adds pairs, so it can only add antinodes: each candidate is scored by the
+// antinodes of its pairs with the existing antennas of that frequency that aren't there already,
+// instead of recomputing the whole map. None if there's no free position left.
+fn best_placement<const N: usize>(map: &Map<N>, frequency: char, rule: Rule,
+                                  goal: Goal) -> Option<Placement<N>> {
+    let existing = antinodes(map, rule);
+    let occupied: HashSet<&Position<N>> = map.antennas.values().flatten().collect();
+    let same_frequency = map.antennas.get(&frequency).map(Vec::as_slice).unwrap_or(&[]);
 
-    let mut temp = p0.clone();
-    loop {
-        temp.0 -= diff.0;
-        temp.1 -= diff.1;
-        if !map.in_map(&temp) {
-            break;
+    let mut best: Option<Placement<N>> = None;
+    for candidate in map.positions() {
+        if occupied.contains(&candidate) {
+            continue;
         }
-        result.push(temp.clone());
-    }
-    temp = p1.clone();
-    loop {
-        temp.0 += diff.0;
-        temp.1 += diff.1;
-        if !map.in_map(&temp) {
-            break;
+        let added: HashSet<Position<N>> = same_frequency.iter()
+            .flat_map(|antenna| pair_antinodes(antenna, &candidate, map, rule))
+            .filter(|p| !existing.contains(p))
+            .collect();
+        let count = existing.len() + added.len();
+
+        match &mut best {
+            Some(placement) if placement.antinodes == count => placement.positions.push(candidate),
+            Some(placement) if (goal == Goal::Maximise) == (placement.antinodes > count) => {},
+            _ => best = Some(Placement { positions: vec![candidate], antinodes: count }),
         }
-        result.push(temp.clone());
+    }
+
+    best
+}
+
+fn find_antinodes<const N: usize>(p0: &Position<N>, p1: &Position<N>) -> (Position<N>, Position<N>) {
+    let diff = p0.diff(p1);
+    (p0.sub(&diff), p1.add(&diff))
+}
+
+// How far apart harmonic antinodes are along the line through two antennas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HarmonicStep {
+    // The distance between the antennas, like the puzzle expects
+    Offset,
+    // The offset divided by its gcd, which hits every grid point on the line (including the
+    // ones between the antennas)
+    Reduced,
+}
+
+fn find_harmonic_antinodes<const N: usize>(p0: &Position<N>, p1: &Position<N>, map: &Map<N>,
+                                           step: HarmonicStep) -> Vec<Position<N>> {
+    let mut result = vec![*p0, *p1];
+    let mut diff = p0.diff(p1);
+    if step == HarmonicStep::Reduced {
+        let divisor = diff.iter().fold(0, |acc, &d| gcd(acc, d));
+        diff = diff.map(|d| d / divisor);
+        result.pop();
+    }
+
+    let mut temp = p0.sub(&diff);
+    while map.in_map(&temp) {
+        result.push(temp);
+        temp = temp.sub(&diff);
+    }
+    temp = match step {
+        HarmonicStep::Offset => p1.add(&diff),
+        HarmonicStep::Reduced => p0.add(&diff),
+    };
+    while map.in_map(&temp) {
+        result.push(temp);
+        temp = temp.add(&diff);
     }
 
     result
 }
 
+fn gcd(a: i32, b: i32) -> i32 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
 
-struct Map {
-    x_size: usize,
-    y_size: usize,
-    antennas: HashMap<char, Vec<Position>>,
+    a
 }
 
-impl Map {
+
+struct Map<const N: usize> {
+    // Number of grid points along each axis
+    size: [usize; N],
+    antennas: HashMap<char, Vec<Position<N>>>,
+}
+
+impl Map<2> {
     fn from_file(path: &str) -> std::io::Result<Self> {
         let f = File::open(path)?;
         let reader = BufReader::new(f);
@@ -113,10 +217,9 @@ impl Map {
             }
 
             for (x, c) in line.chars().enumerate() {
-                match c {
-                    'a'..='z' | 'A'..='Z' |  '0'..='9' => map.entry(c).or_insert(Vec::new())
-                        .push(Position(x.try_into().unwrap(), y.try_into().unwrap())),
-                    _ => {},
+                if is_antenna(c) {
+                    map.entry(c).or_insert(Vec::new())
+                        .push(Position([x.try_into().unwrap(), y.try_into().unwrap()]));
                 }
                 x_max = max(x_max, x);
             }
@@ -124,35 +227,94 @@ impl Map {
         }
 
         Ok(Self {
-            y_size: y_max,
-            x_size: x_max,
+            size: [x_max + 1, y_max + 1],
             antennas: map,
         })
 
     }
+}
+
+impl Map<3> {
+    // 2D layers stacked along z, separated by blank lines
+    fn from_layers_file(path: &str) -> std::io::Result<Self> {
+        let f = File::open(path)?;
+        let reader = BufReader::new(f);
+        let lines = reader.lines();
+
+        let mut map = HashMap::new();
+
+        let mut x_max = 0;
+        let mut y_max = 0;
+        let mut z = 0;
+        let mut y = 0;
 
-    fn in_map(&self, p: &Position) -> bool {
-        if p.0 > self.x_size as i32 || p.1 > self.y_size as i32 {
-            return false;
+        for line in lines {
+            let Ok(line) = line else {
+                continue;
+            };
+            if line.is_empty() {
+                if y > 0 {
+                    z += 1;
+                    y = 0;
+                }
+                continue;
+            }
+
+            for (x, c) in line.chars().enumerate() {
+                if is_antenna(c) {
+                    map.entry(c).or_insert(Vec::new())
+                        .push(Position([x.try_into().unwrap(), y, z]));
+                }
+                x_max = max(x_max, x);
+            }
+            y_max = max(y_max, y as usize);
+            y += 1;
         }
-        if p.0 < 0 || p.1 < 0 {
-            return false;
+        // No trailing blank line after the last layer
+        if y > 0 {
+            z += 1;
         }
-        true
+
+        Ok(Self {
+            size: [x_max + 1, y_max + 1, z as usize],
+            antennas: map,
+        })
+    }
+}
+
+impl<const N: usize> Map<N> {
+    fn in_map(&self, p: &Position<N>) -> bool {
+        p.0.iter().zip(self.size.iter()).all(|(&c, &size)| c >= 0 && c < size as i32)
+    }
+
+    // Every grid point, with x changing fastest
+    fn positions(&self) -> impl Iterator<Item = Position<N>> + '_ {
+        let count = self.size.iter().product::<usize>();
+        (0..count).map(|mut index| {
+            Position(std::array::from_fn(|i| {
+                let c = index % self.size[i];
+                index /= self.size[i];
+                c as i32
+            }))
+        })
     }
 }
 
+fn is_antenna(c: char) -> bool {
+    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_find_antinodes() {
-        assert_eq!(find_antinodes(&Position(4, 3), &Position(5, 5)), (Position(3, 1), Position(6, 7)));
-        assert_eq!(find_antinodes(&Position(4, 3), &Position(8, 4)), (Position(0, 2), Position(12, 5)));
-        assert_eq!(find_antinodes(&Position(8, 4), &Position(5, 5)), (Position(11, 3), Position(2, 6)));
-        assert_eq!(find_antinodes(&Position(6, 5), &Position(8, 8)), (Position(4, 2), Position(10, 11)));
-        assert_eq!(find_antinodes(&Position(8, 8), &Position(6, 5)), (Position(10, 11), Position(4, 2)));
+        assert_eq!(find_antinodes(&Position([4, 3]), &Position([5, 5])), (Position([3, 1]), Position([6, 7])));
+        assert_eq!(find_antinodes(&Position([4, 3]), &Position([8, 4])), (Position([0, 2]), Position([12, 5])));
+        assert_eq!(find_antinodes(&Position([8, 4]), &Position([5, 5])), (Position([11, 3]), Position([2, 6])));
+        assert_eq!(find_antinodes(&Position([6, 5]), &Position([8, 8])), (Position([4, 2]), Position([10, 11])));
+        assert_eq!(find_antinodes(&Position([8, 8]), &Position([6, 5])), (Position([10, 11]), Position([4, 2])));
     }
 
     #[test]
@@ -166,7 +328,113 @@ mod tests {
     #[test]
     fn test_part2() -> std::io::Result<()> {
         let map = Map::from_file("../test_input/day08test.txt")?;
-        assert_eq!(part2(&map), 34);
+        assert_eq!(part2(&map, HarmonicStep::Offset), 34);
+        assert_eq!(part2(&map, HarmonicStep::Reduced), 34);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_size() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day08test.txt")?;
+        assert_eq!(map.size, [12, 12]);
+        assert!(map.in_map(&Position([11, 11])));
+        assert!(!map.in_map(&Position([12, 0])));
+        assert!(!map.in_map(&Position([0, 12])));
+        assert!(!map.in_map(&Position([-1, 0])));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_primitive_offset() -> std::io::Result<()> {
+        // Antennas at (0, 0) and (4, 2)
+        let map = Map::from_file("../test_input/day08test_lattice.txt")?;
+        assert_eq!(gcd(4, 2), 2);
+        assert_eq!(gcd(-6, 9), 3);
+        assert_eq!(part1(&map), 1);
+
+        let offset = find_harmonic_antinodes(&Position([0, 0]), &Position([4, 2]), &map, HarmonicStep::Offset);
+        assert_eq!(offset.into_iter().collect::<HashSet<_>>(),
+                   HashSet::from([Position([0, 0]), Position([4, 2]), Position([8, 4])]));
+        let reduced = find_harmonic_antinodes(&Position([4, 2]), &Position([0, 0]), &map, HarmonicStep::Reduced);
+        assert_eq!(reduced.into_iter().collect::<HashSet<_>>(),
+                   HashSet::from([Position([0, 0]), Position([2, 1]), Position([4, 2]), Position([6, 3]), Position([8, 4])]));
+        assert_eq!(part2(&map, HarmonicStep::Offset), 3);
+        assert_eq!(part2(&map, HarmonicStep::Reduced), 5);
+
+        Ok(())
+    }
+
+    // Recomputes the whole map for every free position
+    fn brute_force_counts(map: &Map<2>, frequency: char, rule: Rule) -> Vec<(Position<2>, usize)> {
+        let mut result = Vec::new();
+        for y in 0..map.size[1] as i32 {
+            for x in 0..map.size[0] as i32 {
+                if map.antennas.values().flatten().any(|p| *p == Position([x, y])) {
+                    continue;
+                }
+                let mut antennas = map.antennas.clone();
+                antennas.entry(frequency).or_default().push(Position([x, y]));
+                let m = Map { size: map.size, antennas };
+                result.push((Position([x, y]), antinodes(&m, rule).len()));
+            }
+        }
+
+        result
+    }
+
+    #[test]
+    fn test_best_placement() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day08test.txt")?;
+        for rule in [Rule::Pairwise, Rule::Harmonic(HarmonicStep::Offset), Rule::Harmonic(HarmonicStep::Reduced)] {
+            for frequency in ['0', 'A'] {
+                let counts = brute_force_counts(&map, frequency, rule);
+                for goal in [Goal::Maximise, Goal::Minimise] {
+                    let optimum = match goal {
+                        Goal::Maximise => counts.iter().map(|(_, c)| *c).max().unwrap(),
+                        Goal::Minimise => counts.iter().map(|(_, c)| *c).min().unwrap(),
+                    };
+                    let positions: Vec<Position<2>> = counts.iter()
+                        .filter(|(_, c)| *c == optimum)
+                        .map(|(p, _)| *p)
+                        .collect();
+                    assert_eq!(best_placement(&map, frequency, rule, goal),
+                               Some(Placement { positions, antinodes: optimum }));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_placement_new_frequency() -> std::io::Result<()> {
+        // A lone antenna has no pairs, so it doesn't matter where it goes
+        let map = Map::from_file("../test_input/day08test_lattice.txt")?;
+        let placement = best_placement(&map, 'b', Rule::Pairwise, Goal::Maximise).unwrap();
+        assert_eq!(placement.antinodes, 1);
+        assert_eq!(placement.positions.len(), 98);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_layers() -> std::io::Result<()> {
+        // a at (0, 0, 0) and (1, 1, 1), b at (0, 3, 0) and (2, 3, 2), in a 4x4x4 volume
+        let volume = Map::from_layers_file("../test_input/day08test_layers.txt")?;
+        assert_eq!(volume.size, [4, 4, 4]);
+        assert_eq!(volume.antennas[&'a'], vec![Position([0, 0, 0]), Position([1, 1, 1])]);
+        assert_eq!(volume.antennas[&'b'], vec![Position([0, 3, 0]), Position([2, 3, 2])]);
+        assert!(volume.in_map(&Position([3, 3, 3])));
+        assert!(!volume.in_map(&Position([3, 3, 4])));
+        assert_eq!(volume.positions().count(), 64);
+
+        assert_eq!(antinodes(&volume, Rule::Pairwise), HashSet::from([Position([2, 2, 2])]));
+        assert_eq!(part2(&volume, HarmonicStep::Offset), 6);
+        // b's offset (2, 0, 2) isn't primitive: (1, 3, 1) between the antennas is new, (3, 3, 3)
+        // one step beyond them was already on a's line
+        assert_eq!(part2(&volume, HarmonicStep::Reduced), 7);
 
         Ok(())
     }
@@ -1,12 +1,11 @@
-use std::cmp::max;
-use std::collections::LinkedList;
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 fn main() -> std::io::Result<()> {
     let f = File::open("input/day09.txt")?;
     let reader = BufReader::new(f);
-    let input = reader.lines().nth(0).unwrap()?;
+    let input = reader.lines().next().unwrap()?;
 
     let part1_start = std::time::Instant::now();
     let part1_result = part1(&input);
@@ -17,127 +16,379 @@ fn main() -> std::io::Result<()> {
     println!("Part1: {}, duration: {:?}", part1_result, part2_start.duration_since(part1_start));
     println!("Part2: {}, duration: {:?}", part2_result, end.duration_since(part2_start));
 
+    let disk_len = parse_disk_map(&input).iter().map(|&d| d as usize).sum();
+    println!("Before:  {}", render(&decode(&input), 100));
+    for (name, split) in [("Part1", true), ("Part2", false)] {
+        let compacted = layout(&defragment(&input, Fit::First, split), disk_len);
+        println!("{}:   {}", name, render(&compacted, 100));
+        match encode(&compacted) {
+            Some(map) if map.len() > 100 => println!("  as a disk map: {} (+{} digits)", &map[..100], map.len() - 100),
+            Some(map) => println!("  as a disk map: {}", map),
+            None => println!("  can't be written as a disk map, files are split or out of order"),
+        }
+    }
+    for split in [false, true] {
+        for fit in [Fit::First, Fit::Best, Fit::Worst, Fit::Next] {
+            let report = report(&defragment(&input, fit, split), disk_len);
+            println!("{:?} fit{}: checksum {}, {} free spans, largest {}", fit,
+                     if split { " (split)" } else { "" },
+                     report.checksum, report.free_spans, report.largest_free_span);
+        }
+    }
+
     Ok(())
 }
 
-fn part1(input: &str) -> usize {
-    let mut result = Vec::new();
-    let mut stack = LinkedList::new();
-    let mut to_fill = LinkedList::new();
-    let mut index = 0usize;
-    let mut file_num= 0usize;
+fn part1(input: &str) -> u128 {
+    let digits = parse_disk_map(input);
+    if digits.is_empty() {
+        return 0;
+    }
 
-    for (i, c) in input.chars().enumerate() {
-        for _ in 0..c.to_digit(10).unwrap() {
-            if i % 2 == 0 {
-                result.push(Some(file_num));
-                stack.push_front((file_num, index));
-            } else {
-                result.push(None);
-                to_fill.push_back(index);
-            }
-            index += 1;
+    // Walk the disk from the left, filling each free span with blocks taken off the end of the
+    // last file that hasn't been moved yet. Everything is summed up on the fly, so the layout
+    // is never built.
+    let mut right = (digits.len() - 1) & !1;
+    let mut right_remaining = digits[right] as usize;
+    let mut pos = 0;
+    let mut checksum = 0;
+
+    for (i, &digit) in digits.iter().enumerate() {
+        if i > right {
+            break;
         }
         if i % 2 == 0 {
-            file_num += 1;
+            let len = if i == right { right_remaining } else { digit as usize };
+            checksum += (i / 2) as u128 * span_sum(pos, len);
+            pos += len;
+            continue;
         }
-    }
 
-    for i in to_fill.into_iter() {
-        if is_compact(&result) {
-            break;
+        let mut free = digit as usize;
+        while free > 0 && right > i {
+            let len = free.min(right_remaining);
+            checksum += (right / 2) as u128 * span_sum(pos, len);
+            pos += len;
+            free -= len;
+            right_remaining -= len;
+            if right_remaining == 0 {
+                right -= 2;
+                right_remaining = digits[right] as usize;
+            }
         }
-        let (val, index) = stack.pop_front().unwrap();
-        result.remove(index);
-        result[i] = Some(val);
     }
 
-    checksum(&result)
+    checksum
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct Block {
     id: usize,
     start: usize,
     len: usize,
 }
 
-impl Block {
-    pub(crate) fn clone(&self) -> Self {
-        Self {
-            id: self.id,
-            start: self.start,
-            len: self.len,
+fn part2(input: &str) -> u128 {
+    let digits = parse_disk_map(input);
+
+    let mut spans = free_spans(&digits);
+    let mut index = digits.iter().map(|&d| d as usize).sum::<usize>();
+
+    // For each file length, the index of the leftmost span that might still fit it. Spans only
+    // ever shrink and files only get further left, so these never have to move backwards and
+    // finding every file's spot costs a single pass over the spans per length.
+    let mut first_fit = [0usize; 10];
+    let mut checksum = 0;
+    for i in (0..digits.len()).rev() {
+        let len = digits[i] as usize;
+        index -= len;
+        if i % 2 == 1 || len == 0 {
+            continue;
+        }
+        let file = Block{id: i / 2, start: index, len};
+
+        let cursor = &mut first_fit[file.len];
+        // Make sure not to move any blocks to the right!
+        while *cursor < spans.len() && spans[*cursor].start < file.start && spans[*cursor].len < file.len {
+            *cursor += 1;
+        }
+        let mut start = file.start;
+        if let Some(span) = spans.get_mut(*cursor).filter(|span| span.start < file.start) {
+            start = span.start;
+            span.start += file.len;
+            span.len -= file.len;
         }
+        // The space the file leaves behind is right of every file still to be moved, so it's
+        // never worth tracking
+        checksum += file.id as u128 * span_sum(start, file.len);
     }
+
+    checksum
 }
 
+// Free spans in disk order. Empty files between two spans make them one longer span.
+fn free_spans(digits: &[u8]) -> Vec<Block> {
+    let mut spans: Vec<Block> = Vec::new();
+    let mut index = 0usize;
+    for (i, &digit) in digits.iter().enumerate() {
+        let len = digit as usize;
+        if i % 2 == 1 && len > 0 {
+            match spans.last_mut() {
+                Some(span) if span.start + span.len == index => span.len += len,
+                _ => spans.push(Block{id: 0, start: index, len}),
+            }
+        }
+        index += len;
+    }
 
-fn part2(input: &str) -> usize {
-    let mut stack = LinkedList::new();
-    let mut free_list = LinkedList::new();  // this is faster as a Vec!
+    spans
+}
 
-    let mut index = 0usize;
-    let mut max_index = 0usize;
-    let mut file_id = 0usize;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fit {
+    // The leftmost span that fits
+    First,
+    // The shortest span that fits, leftmost among those
+    Best,
+    // The longest span, leftmost among those
+    Worst,
+    // The first span that fits after where the last block went, wrapping around to the start
+    Next,
+}
 
-    for (i, c) in input.chars().enumerate() {
-        let len = c.to_digit(10).unwrap() as usize;
-        if i % 2 == 0 {
-            stack.push_front(Block{id: file_id, start: index, len});
-            file_id += 1;
-        } else {
-            free_list.push_back(Block{id: 0, start: index, len});
+// Free spans indexed by length, then by start
+struct FreeIndex(Vec<BTreeSet<usize>>);
+
+impl FreeIndex {
+    fn new(spans: &[Block]) -> Self {
+        let mut index = Self(Vec::new());
+        for span in spans {
+            index.insert(span.start, span.len);
         }
-        index += len;
-        max_index = max(index, max_index);
+
+        index
+    }
+
+    fn insert(&mut self, start: usize, len: usize) {
+        if self.0.len() <= len {
+            self.0.resize(len + 1, BTreeSet::new());
+        }
+        self.0[len].insert(start);
     }
 
-    let mut blocks = LinkedList::new();
+    fn remove(&mut self, span: &Block) {
+        self.0[span.len].remove(&span.start);
+    }
+
+    // The span `fit` picks among those at least `min_len` long that start before `before`
+    fn pick(&self, fit: Fit, min_len: usize, before: usize, cursor: usize) -> Option<Block> {
+        let mut candidates = (min_len.max(1)..self.0.len()).filter_map(|len| {
+            let start = match fit {
+                Fit::Next => self.0[len].range(cursor.min(before)..before).next(),
+                _ => self.0[len].range(..before).next(),
+            };
+            start.map(|&start| Block{id: 0, start, len})
+        });
+
+        match fit {
+            Fit::First => candidates.min_by_key(|span| span.start),
+            Fit::Best => candidates.next(),
+            Fit::Worst => candidates.next_back(),
+            Fit::Next => candidates.min_by_key(|span| span.start)
+                .or_else(|| self.pick(Fit::First, min_len, before.min(cursor), 0)),
+        }
+    }
+}
+
+// Moves files from the highest id down into free spans left of them, using `fit` to choose
+// the span. With `split` files can be broken up, each piece going wherever `fit` would put it
+// if length didn't matter. Best fit still prefers a span the rest of the file fits in, and
+// otherwise takes the longest one to keep the number of pieces down. First fit with splitting
+// is the part1 compaction. Returns where every file (or piece of one) ends up.
+fn defragment(input: &str, fit: Fit, split: bool) -> Vec<Block> {
+    let digits = parse_disk_map(input);
+    let mut free = FreeIndex::new(&free_spans(&digits));
 
-    for block in stack.iter() {
-        let mut moved = false;
-        for free_block in free_list.iter_mut() {
-            // Make sure not to move any blocks to the right!
-            if free_block.start < block.start && free_block.len >= block.len {
-                blocks.push_back(Block{id: block.id, start: free_block.start, len: block.len});
-                free_block.len -= block.len;
-                free_block.start += block.len;
-                moved = true;
+    let mut files = Vec::new();
+    let mut index = 0usize;
+    for (i, &digit) in digits.iter().enumerate() {
+        if i % 2 == 0 && digit > 0 {
+            files.push(Block{id: i / 2, start: index, len: digit as usize});
+        }
+        index += digit as usize;
+    }
+
+    let mut placed = Vec::new();
+    let mut cursor = 0;
+    for file in files.iter().rev() {
+        let mut remaining = file.len;
+        while remaining > 0 {
+            let span = match (split, fit) {
+                (false, _) => free.pick(fit, remaining, file.start, cursor),
+                (true, Fit::Best) => free.pick(fit, remaining, file.start, cursor)
+                    .or_else(|| free.pick(Fit::Worst, 1, file.start, cursor)),
+                (true, _) => free.pick(fit, 1, file.start, cursor),
+            };
+            let Some(span) = span else {
                 break;
+            };
+
+            free.remove(&span);
+            let len = remaining.min(span.len);
+            if span.len > len {
+                free.insert(span.start + len, span.len - len);
             }
+            placed.push(Block{id: file.id, start: span.start, len});
+            cursor = span.start + len;
+            remaining -= len;
         }
-        if !moved {
-            blocks.push_back(block.clone());
+        // Whatever didn't move stays at the front of where the file was
+        if remaining > 0 {
+            placed.push(Block{id: file.id, start: file.start, len: remaining});
         }
     }
 
-    let mut result = vec![None; max_index + 1];
-    for block in blocks.iter() {
-        for i in block.start..(block.start + block.len) {
-            result[i] = Some(block.id);
+    placed
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Report {
+    checksum: u128,
+    // Free space including the space after the last file
+    free_spans: usize,
+    largest_free_span: usize,
+}
+
+fn report(files: &[Block], disk_len: usize) -> Report {
+    let mut files = files.to_vec();
+    files.sort_by_key(|file| file.start);
+
+    let mut free_spans = 0;
+    let mut largest_free_span = 0;
+    let mut pos = 0;
+    for file in files.iter().chain(std::iter::once(&Block{id: 0, start: disk_len, len: 0})) {
+        if file.start > pos {
+            free_spans += 1;
+            largest_free_span = largest_free_span.max(file.start - pos);
         }
+        pos = pos.max(file.start + file.len);
     }
 
-    checksum(&result[..])
+    Report {
+        checksum: files.iter().map(|file| file.id as u128 * span_sum(file.start, file.len)).sum(),
+        free_spans,
+        largest_free_span,
+    }
 }
 
-fn checksum(v: &[Option<usize>]) -> usize {
-    let mut checksum = 0;
-    for (i, n) in v.iter().enumerate() {
-        match n {
-            Some(v) => checksum += i * v,
-            None => {},
+// Expands a dense disk map into one entry per block: the id of the file in it, if any
+fn decode(input: &str) -> Vec<Option<usize>> {
+    let mut layout = Vec::new();
+    for (i, &digit) in parse_disk_map(input).iter().enumerate() {
+        let block = if i % 2 == 0 { Some(i / 2) } else { None };
+        layout.extend(std::iter::repeat_n(block, digit as usize));
+    }
+
+    layout
+}
+
+// Turns a block layout back into a dense disk map. Files have to be in one piece and in id
+// order, which rules out most compacted layouts. Skipped ids become empty files, which is
+// also the only way to get a free span longer than 9 across. None if the layout can't be
+// written down.
+fn encode(layout: &[Option<usize>]) -> Option<String> {
+    let mut result = String::new();
+    let mut next_id = 0;
+    let mut i = 0;
+    while i < layout.len() {
+        let run = layout[i..].iter().take_while(|&&block| block == layout[i]).count();
+        match layout[i] {
+            Some(id) => {
+                if id < next_id || run > 9 {
+                    return None;
+                }
+                // Only free space can come before a file, so a file right after another one
+                // needs an empty span in between
+                if result.len() % 2 == 1 {
+                    result.push('0');
+                }
+                while next_id < id {
+                    result.push_str("00");
+                    next_id += 1;
+                }
+                result.push(char::from(b'0' + run as u8));
+                next_id += 1;
+            },
+            None => {
+                if result.is_empty() {
+                    result.push('0');
+                    next_id += 1;
+                }
+                let mut free = run;
+                loop {
+                    let len = free.min(9);
+                    result.push(char::from(b'0' + len as u8));
+                    free -= len;
+                    // Borrow the id of an empty file to keep going, if the next file leaves one
+                    let borrowable = match layout.get(i + run) {
+                        Some(Some(id)) => *id > next_id,
+                        _ => true,
+                    };
+                    if free == 0 || !borrowable {
+                        break;
+                    }
+                    result.push('0');
+                    next_id += 1;
+                }
+                if free > 0 {
+                    return None;
+                }
+            },
         }
+        i += run;
     }
-    checksum
 
+    Some(result)
+}
+
+// Where every block ends up, given where the files (or pieces of them) are
+fn layout(files: &[Block], disk_len: usize) -> Vec<Option<usize>> {
+    let mut layout = vec![None; disk_len];
+    for file in files {
+        layout[file.start..file.start + file.len].fill(Some(file.id));
+    }
+
+    layout
 }
 
-fn is_compact(v: &Vec<Option<usize>>) -> bool {
-    let total = v.iter().filter(|v| v.is_some()).count();
+// Draws a layout like the puzzle does, files as (the last digit of) their id and free blocks as
+// dots. Anything past `width` blocks is cut off.
+fn render(layout: &[Option<usize>], width: usize) -> String {
+    let mut result: String = layout.iter().take(width)
+        .map(|block| match block {
+            Some(id) => char::from(b'0' + (id % 10) as u8),
+            None => '.',
+        })
+        .collect();
+    if layout.len() > width {
+        result += &format!(" (+{} blocks)", layout.len() - width);
+    }
 
-    v[0..total].iter().all(|v| v.is_some())
+    result
+}
+
+fn parse_disk_map(input: &str) -> Vec<u8> {
+    input.trim().bytes().map(|b| {
+        assert!(b.is_ascii_digit(), "unexpected {:?} in disk map", b as char);
+        b - b'0'
+    }).collect()
+}
+
+// Sum of the positions start..start + len. Checksums outgrow 64 bits somewhere around a few
+// million digits, so everything that goes into them is widened first.
+fn span_sum(start: usize, len: usize) -> u128 {
+    let (start, len) = (start as u128, len as u128);
+    len * start + len * len.saturating_sub(1) / 2
 }
 
 #[cfg(test)]
@@ -148,7 +399,7 @@ mod tests {
     fn test_part1() -> std::io::Result<()> {
         let f = File::open("../test_input/day09test.txt")?;
         let reader = BufReader::new(f);
-        let input = reader.lines().nth(0).unwrap()?;
+        let input = reader.lines().next().unwrap()?;
         assert_eq!(part1(&input), 1928);
 
         Ok(())
@@ -158,9 +409,187 @@ mod tests {
     fn test_part2() -> std::io::Result<()> {
         let f = File::open("../test_input/day09test.txt")?;
         let reader = BufReader::new(f);
-        let input = reader.lines().nth(0).unwrap()?;
+        let input = reader.lines().next().unwrap()?;
         assert_eq!(part2(&input), 2858);
 
         Ok(())
     }
+
+    // The block by block compaction from the puzzle description, on the expanded layout
+    fn reference_part1(input: &str) -> u128 {
+        let mut layout = Vec::new();
+        for (i, c) in input.chars().enumerate() {
+            let len = c.to_digit(10).unwrap() as usize;
+            layout.extend(std::iter::repeat_n(if i % 2 == 0 { Some(i / 2) } else { None }, len));
+        }
+        let (mut left, mut right) = (0, layout.len().saturating_sub(1));
+        while left < right {
+            if layout[left].is_some() {
+                left += 1;
+            } else if layout[right].is_none() {
+                right -= 1;
+            } else {
+                layout.swap(left, right);
+            }
+        }
+
+        layout.iter().enumerate().map(|(i, id)| (i * id.unwrap_or(0)) as u128).sum()
+    }
+
+    // Moves whole files to the leftmost span that fits by scanning the expanded layout
+    fn reference_part2(input: &str) -> u128 {
+        let mut layout = Vec::new();
+        for (i, c) in input.chars().enumerate() {
+            let len = c.to_digit(10).unwrap() as usize;
+            layout.extend(std::iter::repeat_n(if i % 2 == 0 { Some(i / 2) } else { None }, len));
+        }
+        for id in (0..input.len().div_ceil(2)).rev() {
+            let Some(start) = layout.iter().position(|&b| b == Some(id)) else {
+                continue;
+            };
+            let len = layout[start..].iter().take_while(|&&b| b == Some(id)).count();
+            let mut run = 0;
+            for i in 0..start {
+                run = if layout[i].is_none() { run + 1 } else { 0 };
+                if run == len {
+                    for j in 0..len {
+                        layout.swap(i + 1 - len + j, start + j);
+                    }
+                    break;
+                }
+            }
+        }
+
+        layout.iter().enumerate().map(|(i, id)| (i * id.unwrap_or(0)) as u128).sum()
+    }
+
+    // Small pseudo-random disk maps, including zero length files and spans
+    fn random_disk_maps() -> Vec<String> {
+        let mut state = 12345u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        (0..200).map(|_| {
+            let len = 1 + next() % 40;
+            (0..len).map(|_| char::from(b'0' + (next() % 10) as u8)).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        for input in random_disk_maps() {
+            assert_eq!(part1(&input), reference_part1(&input), "{}", input);
+            assert_eq!(part2(&input), reference_part2(&input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_large_disk_map() {
+        let input: String = (0..1_000_000).map(|i| char::from(b'1' + (i * 7 % 9) as u8)).collect();
+        assert_eq!(part1(&input), reference_part1(&input));
+        part2(&input);
+    }
+
+    #[test]
+    fn test_checksum_past_u64() {
+        // A million files of 9 blocks without any free space, so nothing moves and file k
+        // covers 9k..9k + 9: the checksum is the sum of k * (81k + 36)
+        let files = 1_000_000u128;
+        let input = "90".repeat(files as usize);
+        let expected = 81 * (files - 1) * files * (2 * files - 1) / 6 + 36 * (files - 1) * files / 2;
+        assert!(expected > u64::MAX as u128);
+        assert_eq!(part1(&input), expected);
+        assert_eq!(part2(&input), expected);
+        let disk_len = 9 * files as usize;
+        assert_eq!(report(&defragment(&input, Fit::Best, false), disk_len).checksum, expected);
+    }
+
+    #[test]
+    fn test_first_fit_matches_parts() {
+        for input in random_disk_maps() {
+            assert_eq!(report(&defragment(&input, Fit::First, false), 0).checksum, part2(&input), "{}", input);
+            assert_eq!(report(&defragment(&input, Fit::First, true), 0).checksum, part1(&input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_strategies() {
+        // 0...1..23 with 3 two blocks long: the strategies disagree on where 3 goes
+        let input = "1312102";
+        let reports: Vec<Report> = [Fit::First, Fit::Best, Fit::Worst, Fit::Next]
+            .into_iter()
+            .map(|fit| report(&defragment(input, fit, false), 10))
+            .collect();
+        assert_eq!(reports, vec![
+            // 0332.1....
+            Report { checksum: 19, free_spans: 1, largest_free_span: 5 },
+            // 021..33...
+            Report { checksum: 37, free_spans: 2, largest_free_span: 3 },
+            // 0331.2....
+            Report { checksum: 22, free_spans: 2, largest_free_span: 4 },
+            Report { checksum: 19, free_spans: 1, largest_free_span: 5 },
+        ]);
+
+        // With splitting, every fit packs the example into the front
+        for fit in [Fit::First, Fit::Best, Fit::Worst, Fit::Next] {
+            let report = report(&defragment("2333133121414131402", fit, true), 42);
+            assert_eq!((report.free_spans, report.largest_free_span), (1, 14));
+        }
+    }
+
+    #[test]
+    fn test_example_reports() {
+        // 0099811188827773336446555566..............
+        let split = report(&defragment("2333133121414131402", Fit::First, true), 42);
+        assert_eq!(split, Report { checksum: 1928, free_spans: 1, largest_free_span: 14 });
+        // 00992111777.44.333....5555.6666.....8888..
+        let whole = report(&defragment("2333133121414131402", Fit::First, false), 42);
+        assert_eq!(whole, Report { checksum: 2858, free_spans: 6, largest_free_span: 5 });
+    }
+
+    #[test]
+    fn test_render() {
+        let input = "2333133121414131402";
+        assert_eq!(render(&decode(input), 100), "00...111...2...333.44.5555.6666.777.888899");
+        assert_eq!(render(&layout(&defragment(input, Fit::First, true), 42), 100),
+                   "0099811188827773336446555566..............");
+        assert_eq!(render(&layout(&defragment(input, Fit::First, false), 42), 100),
+                   "00992111777.44.333....5555.6666.....8888..");
+        assert_eq!(render(&decode(input), 10), "00...111.. (+32 blocks)");
+        assert_eq!(render(&decode("12345"), 100), "0..111....22222");
+        // Ids past 9 show their last digit
+        assert_eq!(render(&decode("1111111111111111111111"), 100), "0.1.2.3.4.5.6.7.8.9.0.");
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(&decode("2333133121414131402")), Some("2333133121414131402".to_string()));
+        assert_eq!(encode(&decode("12345")), Some("12345".to_string()));
+        assert_eq!(encode(&[]), Some(String::new()));
+        // Free space first means file 0 is empty, files next to each other have no space between
+        assert_eq!(encode(&[None, Some(1), Some(2)]), Some("01101".to_string()));
+        // A skipped id splits a long free span
+        assert_eq!(encode(&[&[Some(0)][..], &[None; 12], &[Some(2)]].concat()), Some("19031".to_string()));
+
+        // Compacted layouts usually aren't in id order any more
+        let compacted = layout(&defragment("2333133121414131402", Fit::First, false), 42);
+        assert_eq!(encode(&compacted), None);
+        assert_eq!(encode(&[Some(0), None, Some(0)]), None);
+        assert_eq!(encode(&[Some(0); 10]), None);
+        assert_eq!(encode(&[&[Some(0)][..], &[None; 10], &[Some(1)]].concat()), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for input in random_disk_maps() {
+            let layout = decode(&input);
+            let encoded = encode(&layout).unwrap();
+            assert_eq!(decode(&encoded), layout, "{}", input);
+            // Empty files are the only thing that can't be told apart
+            if input.chars().step_by(2).all(|c| c != '0') {
+                assert_eq!(encoded, input.trim_end_matches('0'));
+            }
+        }
+    }
 }
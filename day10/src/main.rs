@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -19,7 +19,9 @@ impl Point {
 }
 
 fn main() -> std::io::Result<()> {
-    let map = Map::from_file("input/day10.txt")?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (rules, args) = parse_rules(&args)?;
+    let map = Map::from_file("input/day10.txt")?.with_rules(rules);
 
     let part1 = map.part1();
     let part2 = map.part2();
@@ -27,53 +29,387 @@ fn main() -> std::io::Result<()> {
     println!("Part 1: {}", part1);
     println!("Part 2: {}", part2);
 
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["csv"] => print!("{}", map.reachability_csv()),
+        ["trails", x, y] => {
+            let start = Point{x: parse_number(x)?, y: parse_number(y)?};
+            if !map.in_bounds(&start) {
+                eprintln!("{},{} is outside the map", start.x, start.y);
+                return Ok(());
+            }
+            if map.lookup(&start).is_none() {
+                eprintln!("{},{} is impassable", start.x, start.y);
+                return Ok(());
+            }
+            for trail in map.trails_from(&start) {
+                let steps: Vec<String> = trail.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+                println!("{}", steps.join(" -> "));
+            }
+        },
+        _ => {},
+    }
+
     Ok(())
 }
 
+// Leading `--climb K`, `--descend K`, `--gentle`, `--start H` and `--end H` options, and the
+// arguments after them
+fn parse_rules(args: &[String]) -> std::io::Result<(Rules, &[String])> {
+    let mut rules = Rules::default();
+    let mut rest = args;
+    loop {
+        match rest {
+            [flag, value, tail @ ..] if flag == "--climb" => {
+                rules.step = StepRule::Climb(parse_number(value)?);
+                rest = tail;
+            },
+            [flag, value, tail @ ..] if flag == "--descend" => {
+                rules.step = StepRule::Descend(parse_number(value)?);
+                rest = tail;
+            },
+            [flag, tail @ ..] if flag == "--gentle" => {
+                rules.step = StepRule::Gentle;
+                rest = tail;
+            },
+            [flag, value, tail @ ..] if flag == "--start" => {
+                rules.start = parse_number(value)?;
+                rest = tail;
+            },
+            [flag, value, tail @ ..] if flag == "--end" => {
+                rules.end = parse_number(value)?;
+                rest = tail;
+            },
+            _ => break,
+        }
+    }
+    // Anything left that looks like an option is misspelled, missing its value or in the wrong
+    // place
+    match rest.iter().find(|arg| arg.starts_with("--")) {
+        Some(flag) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                              format!("unknown option {:?}, or it's missing its value", flag))),
+        None => Ok((rules, rest)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> std::io::Result<T> {
+    s.parse().map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?} is not a number or doesn't fit", s))
+    })
+}
+
+#[derive(Debug, PartialEq)]
+struct TrailheadScore {
+    trailhead: Point,
+    // Number of summits reachable from the trailhead
+    score: usize,
+    // Number of distinct trails from the trailhead to any summit
+    rating: u64,
+}
+
+// Which steps a trail can take between neighbouring cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepRule {
+    // Up by 1 to `max`, the puzzle is `Climb(1)`
+    Climb(u32),
+    // Down by 1 to `max`
+    Descend(u32),
+    // Up, down or level by at most 1. Trails could go in circles or wander around a plateau
+    // then, so only the shortest trails from a trailhead to each summit count.
+    Gentle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rules {
+    step: StepRule,
+    // Trails go from a cell at `start` height to one at `end` height, and stop there
+    start: u32,
+    end: u32,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self { step: StepRule::Climb(1), start: 0, end: 9 }
+    }
+}
 
 struct Map {
-    map: Vec<Vec<u32>>,
+    // None where the map is impassable
+    map: Vec<Vec<Option<u32>>>,
+    rules: Rules,
     trailheads: Vec<Point>,
 }
 
 impl Map {
+    // Digits are heights, '.' and '#' are impassable
     fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
         let f = File::open(path)?;
         let reader = BufReader::new(f);
         let lines = reader.lines();
 
         let mut map = Vec::new();
-        let mut trailheads = Vec::new();
 
-        for (y, line) in lines.enumerate() {
+        for line in lines {
             let Ok(line) = line else {
                 continue;
             };
             if line.is_empty() {
                 continue;
             }
-            for (x, c) in line.chars().enumerate() {
-                let num = c.to_digit(10).unwrap();
-                if num == 0 {
-                    trailheads.push(Point{x: x as i32, y: y as i32});
+            let row = line.chars()
+                .map(|c| match c {
+                    '.' | '#' => Ok(None),
+                    _ => c.to_digit(10).map(Some).ok_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("unexpected {:?} in map", c))
+                    }),
+                })
+                .collect::<std::io::Result<Vec<_>>>()?;
+            map.push(row);
+        }
+
+        Ok(Self { map, rules: Rules::default(), trailheads: Vec::new() }.with_rules(Rules::default()))
+    }
+
+    fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self.trailheads = self.cells().filter(|p| self.lookup(p) == Some(rules.start)).collect();
+        self
+    }
+
+    fn part1(&self) -> usize {
+        self.scores().iter().map(|s| s.score).sum()
+    }
+
+    fn part2(&self) -> u64 {
+        self.scores().iter().map(|s| s.rating).sum()
+    }
+
+    // With a climbing or descending rule every step goes the same way, so this can work back
+    // from the summits one height at a time: a cell reaches the summits (and has the trails) of
+    // every neighbour it can step to. Summits are kept as bitsets, and only for the heights a
+    // single step can still bridge. `StepRule::Gentle` trails don't follow the heights, so they
+    // get counted from each trailhead on its own instead.
+    fn scores(&self) -> Vec<TrailheadScore> {
+        let (reversed, max_step) = match self.rules.step {
+            StepRule::Climb(max) => (true, max),
+            StepRule::Descend(max) => (false, max),
+            StepRule::Gentle => {
+                return self.trailheads.iter()
+                    .map(|trailhead| {
+                        let counts = self.trail_counts(trailhead);
+                        TrailheadScore {
+                            trailhead: trailhead.clone(),
+                            score: counts.len(),
+                            rating: counts.values().sum(),
+                        }
+                    })
+                    .collect();
+            },
+        };
+
+        let mut levels: Vec<Vec<Point>> = (0..10).map(|_| Vec::new()).collect();
+        for p in self.cells() {
+            if let Some(height) = self.lookup(&p) {
+                levels[height as usize].push(p);
+            }
+        }
+        // Heights in the order the steps go, backwards
+        let mut order: Vec<usize> = (0..10).collect();
+        if reversed {
+            order.reverse();
+        }
+        let summits = self.summits();
+        let words = summits.len().div_ceil(64);
+
+        let cells = self.map.len() * self.map[0].len();
+        let mut reach: Vec<Vec<u64>> = vec![Vec::new(); cells];
+        let mut trails = vec![0u64; cells];
+        for (i, p) in summits.iter().enumerate() {
+            let mut bits = vec![0u64; words];
+            bits[i / 64] |= 1 << (i % 64);
+            reach[self.index(p)] = bits;
+            trails[self.index(p)] = 1;
+        }
+        for (i, &height) in order.iter().enumerate() {
+            if height as u32 != self.rules.end {
+                for p in levels[height].iter() {
+                    let mut bits = vec![0u64; words];
+                    for n in self.get_neighbors(p).iter().filter(|n| self.passable(p, n)) {
+                        for (word, other) in bits.iter_mut().zip(reach[self.index(n)].iter()) {
+                            *word |= other;
+                        }
+                        trails[self.index(p)] += trails[self.index(n)];
+                    }
+                    reach[self.index(p)] = bits;
+                }
+            }
+            if let Some(&done) = i.checked_sub(max_step as usize).and_then(|j| order.get(j)) {
+                if done as u32 != self.rules.start {
+                    for p in levels[done].iter() {
+                        reach[self.index(p)] = Vec::new();
+                    }
+                }
+            }
+        }
+
+        self.trailheads.iter()
+            .map(|p| TrailheadScore {
+                trailhead: p.clone(),
+                score: reach[self.index(p)].iter().map(|word| word.count_ones() as usize).sum(),
+                rating: trails[self.index(p)],
+            })
+            .collect()
+    }
+
+    // Every distinct trail from `start` to a summit, as the points along it
+    fn trails_from(&self, start: &Point) -> Vec<Vec<Point>> {
+        let (_, order) = self.step_order(start);
+        let mut result = Vec::new();
+        let mut trail = vec![start.clone()];
+        self.extend_trails(&order, &mut trail, &mut result);
+
+        result
+    }
+
+    fn extend_trails(&self, order: &HashMap<usize, i32>, trail: &mut Vec<Point>, result: &mut Vec<Vec<Point>>) {
+        let last = trail.last().unwrap().clone();
+        if self.lookup(&last) == Some(self.rules.end) {
+            result.push(trail.iter().map(Point::clone).collect());
+            return;
+        }
+        for n in self.get_neighbors(&last).into_iter().filter(|n| self.forward(order, &last, n)) {
+            trail.push(n);
+            self.extend_trails(order, trail, result);
+            trail.pop();
+        }
+    }
+
+    // The cells reachable from `start`, sorted so that every trail visits them in that order,
+    // and each cell's place in it. Climbing and descending trails go by height. Gentle trails
+    // go by the number of steps from `start`, which is what limits them to the shortest ones.
+    // Nothing is reachable from an impassable cell, not even the cell itself.
+    fn step_order(&self, start: &Point) -> (Vec<Point>, HashMap<usize, i32>) {
+        if self.lookup(start).is_none() {
+            return (Vec::new(), HashMap::new());
+        }
+        let mut reachable = vec![start.clone()];
+        let mut steps = HashMap::from([(self.index(start), 0)]);
+        let mut i = 0;
+        while i < reachable.len() {
+            let p = reachable[i].clone();
+            i += 1;
+            if self.lookup(&p) == Some(self.rules.end) {
+                continue;
+            }
+            for n in self.get_neighbors(&p).into_iter().filter(|n| self.passable(&p, n)) {
+                if !steps.contains_key(&self.index(&n)) {
+                    steps.insert(self.index(&n), steps[&self.index(&p)] + 1);
+                    reachable.push(n);
                 }
             }
-            map.push(line.chars().map(|c| c.to_digit(10).unwrap()).collect());
         }
 
-        Ok(Self { map, trailheads })
+        let order: HashMap<usize, i32> = reachable.iter()
+            .map(|p| {
+                let height = self.lookup(p).unwrap() as i32;
+                let key = match self.rules.step {
+                    StepRule::Climb(_) => height,
+                    StepRule::Descend(_) => -height,
+                    StepRule::Gentle => steps[&self.index(p)],
+                };
+                (self.index(p), key)
+            })
+            .collect();
+        reachable.sort_by_key(|p| order[&self.index(p)]);
+
+        (reachable, order)
+    }
+
+    // Whether a trail can step from `point0` to `point1`, given the `step_order` it's following
+    fn forward(&self, order: &HashMap<usize, i32>, point0: &Point, point1: &Point) -> bool {
+        self.passable(point0, point1)
+            && order.get(&self.index(point1)) > order.get(&self.index(point0))
+    }
+
+    // Summits in reading order
+    fn summits(&self) -> Vec<Point> {
+        self.cells().filter(|p| self.lookup(p) == Some(self.rules.end)).collect()
+    }
+
+    // Number of trails from `trailhead` to each summit it reaches, by summit index. Trails are
+    // counted forward from the trailhead in step order, so only the part of the map it can
+    // reach gets visited.
+    fn trail_counts(&self, trailhead: &Point) -> HashMap<usize, u64> {
+        let (reachable, order) = self.step_order(trailhead);
+
+        let mut ways: HashMap<usize, u64> = HashMap::from([(self.index(trailhead), 1)]);
+        let mut counts = HashMap::new();
+        for p in reachable.iter() {
+            let here = ways.get(&self.index(p)).copied().unwrap_or(0);
+            if self.lookup(p) == Some(self.rules.end) {
+                counts.insert(self.index(p), here);
+                continue;
+            }
+            for n in self.get_neighbors(p).into_iter().filter(|n| self.forward(&order, p, n)) {
+                *ways.entry(self.index(&n)).or_insert(0) += here;
+            }
+        }
+
+        counts
     }
 
-    fn part1(&self) -> usize {
-        self.trailheads.iter().map(|p| self.explore(p).iter().count()).sum()
+    // Number of trails from each trailhead (rows, in `trailheads` order) to each summit
+    // (columns, in `summits` order)
+    fn reachability(&self) -> Vec<Vec<u64>> {
+        let summits = self.summits();
+
+        self.trailheads.iter()
+            .map(|trailhead| {
+                let counts = self.trail_counts(trailhead);
+                summits.iter()
+                    .map(|summit| counts.get(&self.index(summit)).copied().unwrap_or(0))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The reachability matrix with a header row of summits and a column of trailheads, points
+    // written as x:y
+    fn reachability_csv(&self) -> String {
+        let mut result = String::from("trailhead");
+        for summit in self.summits() {
+            result += &format!(",{}:{}", summit.x, summit.y);
+        }
+        result.push('\n');
+        for (trailhead, row) in self.trailheads.iter().zip(self.reachability()) {
+            result += &format!("{}:{}", trailhead.x, trailhead.y);
+            for trails in row {
+                result += &format!(",{}", trails);
+            }
+            result.push('\n');
+        }
+
+        result
     }
 
-    fn part2(&self) -> usize {
-        self.trailheads.iter().map(|p| self.explore_part2(p)).sum()
+    // Every point on the map in reading order
+    fn cells(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..self.map.len())
+            .flat_map(|y| (0..self.map[0].len()).map(move |x| Point{x: x as i32, y: y as i32}))
+    }
+
+    fn index(&self, point: &Point) -> usize {
+        point.y as usize * self.map[0].len() + point.x as usize
     }
 
     fn passable(&self, point0: &Point, point1: &Point) -> bool {
-        self.lookup(point1) == self.lookup(point0) + 1
+        let (Some(from), Some(to)) = (self.lookup(point0), self.lookup(point1)) else {
+            return false;
+        };
+        match self.rules.step {
+            StepRule::Climb(max) => to > from && to - from <= max,
+            StepRule::Descend(max) => to < from && from - to <= max,
+            StepRule::Gentle => from.abs_diff(to) <= 1,
+        }
     }
 
     fn in_bounds(&self, point: &Point) -> bool {
@@ -83,7 +419,8 @@ impl Map {
             && point.y < self.map.len() as i32
     }
 
-    fn lookup(&self, point: &Point) -> u32 {
+    // None for impassable cells
+    fn lookup(&self, point: &Point) -> Option<u32> {
         if !self.in_bounds(point) {
             // Out of bounds
             panic!();
@@ -91,38 +428,6 @@ impl Map {
         self.map[point.y as usize][point.x as usize]
     }
 
-    // Returns 9s reachable via this starting point
-    fn explore(&self, start: &Point) -> HashSet<Point> {
-        if !self.in_bounds(start) {
-            // Out of bounds
-            return HashSet::new();
-        }
-        if self.lookup(start) == 9 {
-            let mut result = HashSet::new();
-            result.insert(start.clone());
-            return result;
-        }
-
-        self.get_neighbors(start).into_iter()
-            .filter(|p| self.passable(start, p))
-            .flat_map(|p| self.explore(&p))
-            .collect::<HashSet<_>>()
-    }
-
-    fn explore_part2(&self, start: &Point) -> usize {
-        if !self.in_bounds(start) {
-            // Out of bounds
-            return 0;
-        }
-        if self.lookup(start) == 9 {
-            return 1;
-        }
-        self.get_neighbors(start).into_iter()
-            .filter(|p| self.passable(start, p))
-            .map(|p| self.explore_part2(&p))
-            .sum()
-    }
-
     fn get_neighbors(&self, start: &Point) -> Vec<Point> {
         vec![Point{x: start.x + 1, y: start.y},
              Point{x: start.x - 1, y: start.y},
@@ -136,6 +441,7 @@ impl Map {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     fn test_part1() -> std::io::Result<()> {
@@ -152,5 +458,149 @@ mod tests {
 
         Ok(())
     }
-}
 
+    #[test]
+    fn test_scores() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day10test.txt")?;
+        let scores: Vec<(usize, u64)> = map.scores().iter().map(|s| (s.score, s.rating)).collect();
+        assert_eq!(scores, vec![(5, 20), (6, 24), (5, 10), (3, 4), (1, 1), (3, 4), (5, 5), (3, 8), (5, 5)]);
+        assert_eq!(map.scores()[0].trailhead, Point{x: 2, y: 0});
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simple() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day10test_simple.txt")?;
+        assert_eq!(map.part1(), 1);
+        assert_eq!(map.part2(), 16);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trails_from() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day10test.txt")?;
+        for score in map.scores() {
+            let trails = map.trails_from(&score.trailhead);
+            assert_eq!(trails.len() as u64, score.rating);
+            for trail in trails.iter() {
+                assert_eq!(trail.len(), 10);
+                assert_eq!(trail[0], score.trailhead);
+                assert!(trail.windows(2).all(|w| map.passable(&w[0], &w[1])));
+            }
+            let summits: HashSet<&Point> = trails.iter().map(|trail| &trail[9]).collect();
+            assert_eq!(summits.len(), score.score);
+        }
+
+        let map = Map::from_file("../test_input/day10test_simple.txt")?;
+        let trails = map.trails_from(&Point{x: 0, y: 0});
+        assert_eq!(trails.len(), 16);
+        assert!(trails.contains(&(0..4).map(|x| Point{x, y: 0})
+            .chain([Point{x: 3, y: 1}, Point{x: 3, y: 2}, Point{x: 3, y: 3}])
+            .chain((0..3).rev().map(|x| Point{x, y: 3}))
+            .collect()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reachability() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day10test.txt")?;
+        let matrix = map.reachability();
+        assert_eq!(matrix.len(), 9);
+        for (row, score) in matrix.iter().zip(map.scores()) {
+            assert_eq!(row.iter().sum::<u64>(), score.rating);
+            assert_eq!(row.iter().filter(|&&trails| trails > 0).count(), score.score);
+        }
+
+        let map = Map::from_file("../test_input/day10test_simple.txt")?;
+        assert_eq!(map.reachability_csv(), "trailhead,0:3\n0:0,16\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_impassable() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day10test_impassable.txt")?;
+        assert_eq!(map.part1(), 4);
+        assert_eq!(map.lookup(&Point{x: 0, y: 1}), None);
+        assert!(map.trails_from(&Point{x: 0, y: 1}).is_empty());
+        assert!(map.trail_counts(&Point{x: 0, y: 1}).is_empty());
+
+        let map = Map::from_file("../test_input/day10test_rating.txt")?;
+        assert_eq!(map.part2(), 3);
+        assert_eq!(map.trails_from(&Point{x: 5, y: 0}).len(), 3);
+
+        let err = Map::from_file("../test_input/day10test_invalid.txt").err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_descend() -> std::io::Result<()> {
+        let rules = Rules { step: StepRule::Descend(1), start: 9, end: 0 };
+        let map = Map::from_file("../test_input/day10test.txt")?.with_rules(rules);
+        assert_eq!(map.trailheads.len(), 7);
+        assert_eq!(map.part1(), 36);
+        assert_eq!(map.part2(), 81);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_rules() -> std::io::Result<()> {
+        for rules in [
+            Rules { step: StepRule::Climb(2), ..Rules::default() },
+            Rules { step: StepRule::Climb(3), start: 1, end: 8 },
+            Rules { step: StepRule::Descend(2), start: 8, end: 2 },
+            Rules { step: StepRule::Gentle, start: 3, end: 5 },
+        ] {
+            let map = Map::from_file("../test_input/day10test.txt")?.with_rules(rules);
+            let matrix = map.reachability();
+            for (score, row) in map.scores().iter().zip(matrix) {
+                let trails = map.trails_from(&score.trailhead);
+                let summits: HashSet<&Point> = trails.iter().map(|trail| trail.last().unwrap()).collect();
+                assert_eq!(trails.len() as u64, score.rating);
+                assert_eq!(summits.len(), score.score);
+                assert_eq!(row.iter().sum::<u64>(), score.rating);
+                assert!(trails.iter().all(|trail| trail.windows(2).all(|w| map.passable(&w[0], &w[1]))));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let args: Vec<String> = ["--descend", "2", "--start", "8", "trails", "1", "2"].map(String::from).to_vec();
+        let (rules, rest) = parse_rules(&args).unwrap();
+        assert_eq!(rules, Rules { step: StepRule::Descend(2), start: 8, end: 9 });
+        assert_eq!(rest, &args[4..]);
+        assert!(parse_rules(&["--climb".to_string(), "-1".to_string()]).is_err());
+        assert!(parse_number::<i32>("x").is_err());
+        for args in [&["--climb"][..], &["--gentle", "--end"], &["--clmb", "2"], &["csv", "--gentle"]] {
+            let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+            assert_eq!(parse_rules(&args).unwrap_err().kind(), std::io::ErrorKind::InvalidInput, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_gentle() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day10test_gentle.txt")?;
+        let rules = Rules { step: StepRule::Climb(1), start: 0, end: 2 };
+        let climb = map.with_rules(rules);
+        assert_eq!((climb.part1(), climb.part2()), (1, 1));
+        let gentle = climb.with_rules(Rules { step: StepRule::Gentle, ..rules });
+        // The detour along the bottom row isn't a shortest trail
+        assert_eq!((gentle.part1(), gentle.part2()), (1, 1));
+
+        // Two equally short ways around the wall, both over level ground
+        let map = Map::from_file("../test_input/day10test_gentle2.txt")?.with_rules(Rules { step: StepRule::Gentle, ..rules });
+        assert_eq!((map.part1(), map.part2()), (1, 2));
+        assert_eq!(map.trails_from(&Point{x: 0, y: 0}).len(), 2);
+
+        Ok(())
+    }
+}
@@ -3,31 +3,315 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-fn main() -> std::io::Result<()> {
-    let mut stones = Stones::from_file("input/day11.txt")?;
+// The integer types stone values and counts are kept in. Arithmetic is checked so a run that
+// outgrows the type stops with an `Overflow` instead of wrapping or panicking.
+trait Value: Copy + Eq + Ord + std::hash::Hash + std::fmt::Display + std::str::FromStr {
+    const ZERO: Self;
+
+    fn from_u64(n: u64) -> Self;
+    fn to_u128(self) -> u128;
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn is_multiple_of(self, rhs: Self) -> bool;
+    fn digits(self) -> u32;
+    // The number with its last `n` digits cut off, and those digits
+    fn split_digits(self, n: u32) -> (Self, Self);
+}
+
+macro_rules! impl_value {
+    ($($t:ty),*) => {$(
+        impl Value for $t {
+            const ZERO: Self = 0;
 
-    for _ in 0..25 {
-        stones.update();
+            fn from_u64(n: u64) -> Self {
+                n as $t
+            }
+
+            fn to_u128(self) -> u128 {
+                self as u128
+            }
+
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_add(self, rhs)
+            }
+
+            fn checked_mul(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_mul(self, rhs)
+            }
+
+            fn is_multiple_of(self, rhs: Self) -> bool {
+                <$t>::is_multiple_of(self, rhs)
+            }
+
+            fn digits(self) -> u32 {
+                self.checked_ilog10().unwrap_or(0) + 1
+            }
+
+            fn split_digits(self, n: u32) -> (Self, Self) {
+                let divisor = (10 as $t).pow(n);
+                (self / divisor, self % divisor)
+            }
+        }
+    )*};
+}
+
+impl_value!(u64, u128);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    // A stone's value doesn't fit
+    Value,
+    // The number of stones with some value, or in total, doesn't fit
+    Count,
+    // The stones reach more distinct values than the given limit
+    ValueSet(usize),
+}
+
+impl std::fmt::Display for Overflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Overflow::Value => write!(f, "stone value overflow"),
+            Overflow::Count => write!(f, "stone count overflow"),
+            Overflow::ValueSet(limit) => write!(f, "more than {} distinct stone values", limit),
+        }
     }
-    let part1: u64 = stones.stones.values().sum();
+}
 
-    for _ in 0..50 {
-        stones.update();
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // --wide keeps values and counts in u128
+    let wide = args.iter().any(|arg| arg == "--wide");
+    // --stats prints the distinct values, largest value and stone count after every blink
+    let stats = args.iter().any(|arg| arg == "--stats");
+    // --blinks N counts the stones after N blinks from the transition matrix, exactly or, with
+    // --mod P, modulo the prime P (below 2^32)
+    let mut blinks = None;
+    let mut modulus = None;
+    let mut path = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--blinks" => blinks = rest.next().map(|n| parse_number::<u64>(n)).transpose()?,
+            "--mod" => modulus = rest.next().map(|p| parse_number::<u32>(p)).transpose()?,
+            _ if !arg.starts_with("--") => path = Some(arg),
+            _ => {},
+        }
+    }
+    if modulus.is_some() && blinks.is_none() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "--mod only works with --blinks"));
     }
-    let part2: u64 = stones.stones.values().sum();
+    if let Some(p) = modulus.filter(|&p| !is_prime(p)) {
+        eprintln!("--mod takes a prime, {} isn't one", p);
+        return Ok(());
+    }
+    let rules = match path {
+        Some(path) => RuleSet::from_file(path)?,
+        None => RuleSet::default(),
+    };
 
-    println!("Part1: {}", part1);
-    println!("Part2: {}", part2);
+    if wide {
+        let stones = Stones::<u128>::from_file("input/day11.txt")?.with_rules(rules);
+        match blinks {
+            Some(n) => fast_forward(&stones, n, modulus),
+            None => run(stones, stats),
+        }
+    } else {
+        let stones = Stones::<u64>::from_file("input/day11.txt")?.with_rules(rules);
+        match blinks {
+            Some(n) => fast_forward(&stones, n, modulus),
+            None => run(stones, stats),
+        }
+    }
 
     Ok(())
 }
 
+fn parse_number<T: std::str::FromStr>(s: &str) -> std::io::Result<T> {
+    s.parse().map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?} is not a number or doesn't fit", s))
+    })
+}
+
+fn fast_forward<T: Value>(stones: &Stones<T>, n: u64, modulus: Option<u32>) {
+    let transitions = match Transitions::discover(stones, 100_000) {
+        Ok(transitions) => transitions,
+        Err(overflow) => {
+            println!("{}", overflow);
+            return;
+        },
+    };
+    println!("{} distinct values", transitions.values.len());
+    match modulus {
+        Some(p) => match transitions.count_mod(stones, n, p) {
+            Some(count) => println!("After {} blinks: {} (mod {})", n, count, p),
+            None => println!("{} isn't prime", p),
+        },
+        None => match transitions.count_exact(stones, n) {
+            Ok(count) => println!("After {} blinks: {}", n, count),
+            Err(overflow) => println!("{} (try --wide or --mod)", overflow),
+        },
+    }
+}
+
+fn run<T: Value>(mut stones: Stones<T>, stats: bool) {
+    for blink in 1..=75 {
+        if let Err(overflow) = stones.update() {
+            println!("{} in blink {} (try --wide)", overflow, blink);
+            return;
+        }
+        if stats {
+            match stones.stats() {
+                Ok(s) => println!("Blink {}: {} distinct values, max {}, {} stones",
+                                  blink, s.distinct, s.max_value, s.total),
+                Err(overflow) => println!("Blink {}: {}", blink, overflow),
+            }
+        }
+        if blink == 25 || blink == 75 {
+            match stones.total() {
+                Ok(total) => println!("Part{}: {}", if blink == 25 { 1 } else { 2 }, total),
+                Err(overflow) => println!("{} after blink {} (try --wide)", overflow, blink),
+            }
+        }
+    }
+}
+
+// Which stones a rule applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Predicate {
+    Any,
+    Equals(u64),
+    EvenDigits,
+    Digits(u32),
+    DivisibleBy(u64),
+}
+
+impl Predicate {
+    fn matches<T: Value>(&self, num: T) -> bool {
+        match *self {
+            Predicate::Any => true,
+            Predicate::Equals(n) => num == T::from_u64(n),
+            Predicate::EvenDigits => num.digits().is_multiple_of(2),
+            Predicate::Digits(n) => num.digits() == n,
+            Predicate::DivisibleBy(n) => n != 0 && num.is_multiple_of(T::from_u64(n)),
+        }
+    }
+}
+
+// What a stone a rule applies to turns into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transform {
+    Set(u64),
+    Add(u64),
+    Multiply(u64),
+    // Left and right halves of the digits, dropping leading zeroes. Stones with an odd number of
+    // digits give the extra one to the right half.
+    Split,
+}
+
+impl Transform {
+    fn apply<T: Value>(&self, num: T) -> Result<(T, Option<T>), Overflow> {
+        let single = |value: Option<T>| value.map(|value| (value, None)).ok_or(Overflow::Value);
+        match *self {
+            Transform::Set(n) => Ok((T::from_u64(n), None)),
+            Transform::Add(n) => single(num.checked_add(T::from_u64(n))),
+            Transform::Multiply(n) => single(num.checked_mul(T::from_u64(n))),
+            Transform::Split => {
+                let (left, right) = num.split_digits(num.digits() - num.digits() / 2);
+                Ok((left, Some(right)))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    when: Predicate,
+    then: Transform,
+}
+
+// Rules in priority order, the first that matches a stone decides what happens to it. Stones
+// that match none stay as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RuleSet(Vec<Rule>);
+
+impl Default for RuleSet {
+    // The puzzle's rules
+    fn default() -> Self {
+        Self(vec![
+            Rule { when: Predicate::Equals(0), then: Transform::Set(1) },
+            Rule { when: Predicate::EvenDigits, then: Transform::Split },
+            Rule { when: Predicate::Any, then: Transform::Multiply(2024) },
+        ])
+    }
+}
+
+impl RuleSet {
+    fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    // One `<predicate> => <transform>` rule per line, blank lines and `#` comments are skipped.
+    // Predicates are `any`, `eq N`, `even_digits`, `digits N` and `div N`, transforms are
+    // `set N`, `add N`, `mul N` and `split`.
+    fn parse(config: &str) -> std::io::Result<Self> {
+        let mut rules = Vec::new();
+
+        for (i, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |what: &str| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                    format!("line {}: {} in {:?}", i + 1, what, line))
+            };
+
+            let (when, then) = line.split_once("=>").ok_or_else(|| invalid("missing =>"))?;
+            let when = match when.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["any"] => Predicate::Any,
+                ["eq", n] => Predicate::Equals(n.parse().map_err(|_| invalid("bad number"))?),
+                ["even_digits"] => Predicate::EvenDigits,
+                ["digits", n] => Predicate::Digits(n.parse().map_err(|_| invalid("bad number"))?),
+                ["div", n] => Predicate::DivisibleBy(n.parse().map_err(|_| invalid("bad number"))?),
+                _ => return Err(invalid("unknown predicate")),
+            };
+            let then = match then.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["set", n] => Transform::Set(n.parse().map_err(|_| invalid("bad number"))?),
+                ["add", n] => Transform::Add(n.parse().map_err(|_| invalid("bad number"))?),
+                ["mul", n] => Transform::Multiply(n.parse().map_err(|_| invalid("bad number"))?),
+                ["split"] => Transform::Split,
+                _ => return Err(invalid("unknown transform")),
+            };
+            rules.push(Rule { when, then });
+        }
+
+        Ok(Self(rules))
+    }
+
+    fn apply<T: Value>(&self, num: T) -> Result<(T, Option<T>), Overflow> {
+        match self.0.iter().find(|rule| rule.when.matches(num)) {
+            Some(rule) => rule.then.apply(num),
+            None => Ok((num, None)),
+        }
+    }
+}
+
+// Where the stones stand after a blink
+#[derive(Debug, PartialEq)]
+struct BlinkStats<T> {
+    distinct: usize,
+    max_value: T,
+    total: T,
+}
+
 #[derive(Debug)]
-struct Stones {
-    stones: HashMap<u64, u64>,
+struct Stones<T> {
+    // Number of stones with each value
+    stones: HashMap<T, T>,
+    rules: RuleSet,
 }
 
-impl Stones {
+impl<T: Value> Stones<T> {
     fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
         let f = File::open(path)?;
         let reader = BufReader::new(f);
@@ -43,41 +327,276 @@ impl Stones {
                 continue;
             }
 
-            for s in line.trim().split_whitespace() {
-                *stones.entry(s.parse().unwrap()).or_insert(0) += 1;
+            for s in line.split_whitespace() {
+                let num = s.parse::<T>().map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                        format!("{:?} is not a number or doesn't fit", s))
+                })?;
+                let count: &mut T = stones.entry(num).or_insert(T::ZERO);
+                *count = count.checked_add(T::from_u64(1)).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "too many stones")
+                })?;
             }
         }
 
-        Ok(Self { stones })
+        Ok(Self { stones, rules: RuleSet::default() })
+    }
+
+    fn with_rules(mut self, rules: RuleSet) -> Self {
+        self.rules = rules;
+        self
     }
 
-    fn update(&mut self) {
+    // Leaves the stones as they were on overflow
+    fn update(&mut self) -> Result<(), Overflow> {
         let mut result = HashMap::new();
-        for entry in &self.stones {
-            for new_entry in Self::update_one_num(*entry.0) {
-                *result.entry(new_entry.0).or_insert(0) += new_entry.1 * entry.1;
+        let mut add = |num: T, count: T| {
+            let total: &mut T = result.entry(num).or_insert(T::ZERO);
+            *total = total.checked_add(count).ok_or(Overflow::Count)?;
+            Ok(())
+        };
+        for (&num, &count) in &self.stones {
+            let (left, right) = self.rules.apply(num)?;
+            add(left, count)?;
+            if let Some(right) = right {
+                add(right, count)?;
             }
         }
 
         self.stones = result;
+        Ok(())
     }
 
-    fn update_one_num(num: u64) -> HashMap<u64, u64> {
-        let mut result = HashMap::new();
-        if num == 0 {
-            result.insert(1, 1);
-            return result;
+    fn total(&self) -> Result<T, Overflow> {
+        self.stones.values().try_fold(T::ZERO, |sum, &count| sum.checked_add(count).ok_or(Overflow::Count))
+    }
+
+    fn stats(&self) -> Result<BlinkStats<T>, Overflow> {
+        Ok(BlinkStats {
+            distinct: self.stones.len(),
+            max_value: self.stones.keys().copied().max().unwrap_or(T::ZERO),
+            total: self.total()?,
+        })
+    }
+}
+
+// The closed set of values the stones can take under their rules, and how many stones of each
+// value one stone becomes in a blink. Values are in discovery order, the seeds first.
+#[derive(Debug)]
+struct Transitions<T> {
+    values: Vec<T>,
+    // Sparse rows of the transition matrix: (target value index, stones per stone)
+    edges: Vec<Vec<(usize, u64)>>,
+}
+
+impl<T: Value> Transitions<T> {
+    // Fails on overflow or if the set has more than `limit` values, since some rules (like
+    // `any => add 1`) never close
+    fn discover(stones: &Stones<T>, limit: usize) -> Result<Self, Overflow> {
+        let mut seeds: Vec<T> = stones.stones.keys().copied().collect();
+        seeds.sort();
+        let mut index: HashMap<T, usize> = HashMap::new();
+        let mut values = Vec::new();
+        let mut edges: Vec<Vec<(usize, u64)>> = Vec::new();
+        for seed in seeds {
+            index.insert(seed, values.len());
+            values.push(seed);
+        }
+
+        let mut i = 0;
+        while i < values.len() {
+            let (left, right) = stones.rules.apply(values[i])?;
+            let mut row: Vec<(usize, u64)> = Vec::new();
+            for next in std::iter::once(left).chain(right) {
+                let j = *index.entry(next).or_insert_with(|| {
+                    values.push(next);
+                    values.len() - 1
+                });
+                if values.len() > limit {
+                    return Err(Overflow::ValueSet(limit));
+                }
+                match row.iter_mut().find(|(target, _)| *target == j) {
+                    Some((_, count)) => *count += 1,
+                    None => row.push((j, 1)),
+                }
+            }
+            edges.push(row);
+            i += 1;
         }
-        let num_string = num.to_string();
-        if num_string.len() % 2 == 0 {
-            result.insert(num_string[0..num_string.len() / 2].parse::<u64>().unwrap(), 1);
-            *result.entry(num_string[num_string.len() / 2..].parse::<u64>().unwrap()).or_insert(0) += 1;
-            return result;
+
+        Ok(Self { values, edges })
+    }
+
+    // Stones of each value (by index) at the start
+    fn initial(&self, stones: &Stones<T>) -> Vec<T> {
+        self.values.iter().map(|v| stones.stones.get(v).copied().unwrap_or(T::ZERO)).collect()
+    }
+
+    // One blink at a time through the sparse rows, for moderate `n`
+    fn count_exact(&self, stones: &Stones<T>, n: u64) -> Result<T, Overflow> {
+        let mut counts = self.initial(stones);
+        for _ in 0..n {
+            let mut next = vec![T::ZERO; counts.len()];
+            for (row, &count) in self.edges.iter().zip(counts.iter()) {
+                if count == T::ZERO {
+                    continue;
+                }
+                for &(j, per_stone) in row {
+                    let added = count.checked_mul(T::from_u64(per_stone)).ok_or(Overflow::Count)?;
+                    next[j] = next[j].checked_add(added).ok_or(Overflow::Count)?;
+                }
+            }
+            counts = next;
+        }
+
+        counts.iter().try_fold(T::ZERO, |sum, &count| sum.checked_add(count).ok_or(Overflow::Count))
+    }
+
+    // The stone count after each of the first `blinks` blinks (from 0), modulo `p`
+    fn counts_mod(&self, stones: &Stones<T>, blinks: usize, p: u32) -> Vec<u64> {
+        let p = p as u64;
+        let mut counts: Vec<u64> = self.initial(stones).iter()
+            .map(|count| (count.to_u128() % p as u128) as u64)
+            .collect();
+        let mut result = Vec::with_capacity(blinks);
+        for _ in 0..blinks {
+            result.push(counts.iter().fold(0, |sum, &count| (sum + count) % p));
+            let mut next = vec![0; counts.len()];
+            for (row, &count) in self.edges.iter().zip(counts.iter()) {
+                for &(j, per_stone) in row {
+                    next[j] = (next[j] + count * (per_stone % p)) % p;
+                }
+            }
+            counts = next;
         }
-        result.insert(num * 2024, 1);
 
         result
     }
+
+    // The stone count after `n` blinks modulo the prime `p`, for any `n`. This is the count
+    // vector times M^n for the transition matrix M, but it doesn't square M: the closed sets
+    // have thousands of values, nearly all of them on cycles, so M^2 is dense and every squaring
+    // costs O(N^3), minutes per step. Instead, the counts follow a linear recurrence no longer
+    // than N (M's minimal polynomial), which Berlekamp-Massey finds from the first 2N counts.
+    // M^n then reduces to x^n modulo that recurrence, the same power in O(L^2 log n). Finding
+    // the recurrence needs inverses mod `p`, hence a prime. None if `p` isn't one.
+    fn count_mod(&self, stones: &Stones<T>, n: u64, p: u32) -> Option<u32> {
+        if !is_prime(p) {
+            return None;
+        }
+        let terms = self.counts_mod(stones, 2 * self.values.len() + 2, p);
+        if let Some(&count) = usize::try_from(n).ok().and_then(|n| terms.get(n)) {
+            return Some(count as u32);
+        }
+        let recurrence = berlekamp_massey(&terms, p)?;
+        let power = power_mod(&recurrence, n, p);
+
+        Some(power.iter().zip(terms.iter()).fold(0, |sum, (&x, &term)| (sum + x * term) % p as u64) as u32)
+    }
+}
+
+// a^(p - 2), which is a's inverse by Fermat's little theorem for prime `p`
+fn inverse_mod(a: u64, p: u64) -> Option<u64> {
+    let mut result = 1;
+    let mut base = a % p;
+    let mut exponent = p.checked_sub(2)?;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % p;
+        }
+        base = base * base % p;
+        exponent >>= 1;
+    }
+
+    (a % p * result % p == 1).then_some(result)
+}
+
+// Trial division, at most 2^15 odd divisors below 2^32
+fn is_prime(p: u32) -> bool {
+    p >= 2 && (2..).take_while(|&d: &u64| d * d <= p as u64).all(|d| !(p as u64).is_multiple_of(d))
+}
+
+// The shortest recurrence s[k] = r[0] * s[k - 1] + ... + r[L - 1] * s[k - L] (mod p) that
+// generates `terms`. None if an inverse mod `p` is missing.
+fn berlekamp_massey(terms: &[u64], p: u32) -> Option<Vec<u64>> {
+    let p = p as u64;
+    // Connection polynomials, the current one and the one before the last length change
+    let mut current = vec![1u64];
+    let mut previous = vec![1u64];
+    let mut length = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1;
+
+    for k in 0..terms.len() {
+        let discrepancy = (0..=length.min(current.len() - 1))
+            .fold(0, |sum, i| (sum + current[i] * terms[k - i]) % p);
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = discrepancy * inverse_mod(previous_discrepancy, p)? % p;
+        let before = current.clone();
+        if current.len() < previous.len() + shift {
+            current.resize(previous.len() + shift, 0);
+        }
+        for (i, &b) in previous.iter().enumerate() {
+            current[i + shift] = (current[i + shift] + p - scale * b % p) % p;
+        }
+        if 2 * length <= k {
+            length = k + 1 - length;
+            previous = before;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+    }
+
+    current.resize(length + 1, 0);
+    Some(current[1..].iter().map(|&c| (p - c) % p).collect())
+}
+
+// x^n modulo the recurrence's characteristic polynomial, as coefficients of 1, x, x^2, ...
+fn power_mod(recurrence: &[u64], n: u64, p: u32) -> Vec<u64> {
+    let p = p as u64;
+    let length = recurrence.len();
+    if length == 0 {
+        return Vec::new();
+    }
+    // Products of two coefficients fit in a u64 since p < 2^32, and sums of up to 2^64 of them
+    // in a u128, so only whole coefficients get reduced
+    let multiply = |a: &[u64], b: &[u64]| {
+        let mut product = vec![0u128; 2 * length - 1];
+        for (i, &x) in a.iter().enumerate().filter(|(_, &x)| x != 0) {
+            for (j, &y) in b.iter().enumerate() {
+                product[i + j] += (x * y) as u128;
+            }
+        }
+        // x^L = r[0] x^(L - 1) + ... + r[L - 1]
+        for k in (length..product.len()).rev() {
+            let top = (product[k] % p as u128) as u64;
+            for (i, &r) in recurrence.iter().enumerate() {
+                product[k - 1 - i] += (top * r) as u128;
+            }
+        }
+        product[..length].iter().map(|&c| (c % p as u128) as u64).collect::<Vec<_>>()
+    };
+
+    let mut result = vec![0; length];
+    result[0] = 1 % p;
+    let mut x = vec![0; length];
+    x[length.min(2) - 1] = 1;
+    if length == 1 {
+        x[0] = recurrence[0];
+    }
+    for bit in (0..u64::BITS - n.leading_zeros()).rev() {
+        result = multiply(&result, &result);
+        if (n >> bit) & 1 == 1 {
+            result = multiply(&result, &x);
+        }
+    }
+
+    result
 }
 
 #[cfg(test)]
@@ -86,12 +605,178 @@ mod tests {
 
     #[test]
     fn test_part1() -> std::io::Result<()> {
-        let mut stones = Stones::from_file("../test_input/day11test2.txt")?;
+        let mut stones = Stones::<u64>::from_file("../test_input/day11test2.txt")?;
         for _ in 0..25 {
-            stones.update();
+            stones.update().unwrap();
         }
         assert_eq!(stones.stones.values().sum::<u64>(), 55312);
 
         Ok(())
     }
+
+    #[test]
+    fn test_rules() -> std::io::Result<()> {
+        let rules = RuleSet::from_file("../test_input/day11rules.txt")?;
+        assert_eq!(rules, RuleSet::default());
+        assert_eq!(rules.apply(0u64), Ok((1, None)));
+        assert_eq!(rules.apply(1000u64), Ok((10, Some(0))));
+        assert_eq!(rules.apply(253u64), Ok((253 * 2024, None)));
+
+        let mut stones = Stones::<u64>::from_file("../test_input/day11test2.txt")?.with_rules(rules);
+        for _ in 0..6 {
+            stones.update().unwrap();
+        }
+        assert_eq!(stones.stones.values().sum::<u64>(), 22);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_variants() -> std::io::Result<()> {
+        let rules = RuleSet::parse("digits 3 => split\ndiv 5 => add 1 # comment\n\nany => mul 3\n")?;
+        assert_eq!(rules.apply(123u64), Ok((1, Some(23))));
+        assert_eq!(rules.apply(10u64), Ok((11, None)));
+        assert_eq!(rules.apply(7u64), Ok((21, None)));
+        assert_eq!(RuleSet(Vec::new()).apply(7u64), Ok((7, None)));
+
+        let mut stones = Stones::<u64>::from_file("../test_input/day11test.txt")?.with_rules(rules);
+        stones.update().unwrap();
+        let mut values: Vec<u64> = stones.stones.keys().copied().collect();
+        values.sort();
+        assert_eq!(values, vec![1, 3, 9, 11, 99, 297]);
+
+        for config in ["eq 0 -> set 1", "odd => split", "eq x => set 1", "any => mul"] {
+            let err = RuleSet::parse(config).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow() -> std::io::Result<()> {
+        // 19 digits, so the first blink multiplies by 2024
+        let mut stones = Stones::<u64>::from_file("../test_input/day11test_big.txt")?;
+        assert_eq!(stones.update(), Err(Overflow::Value));
+        assert_eq!(stones.total(), Ok(1));
+        let mut stones = Stones::<u128>::from_file("../test_input/day11test_big.txt")?;
+        stones.update().unwrap();
+        assert_eq!(stones.stones.keys().next(), Some(&(9_999_999_999_999_999_999u128 * 2024)));
+
+        // Every stone splits into two zeroes
+        let rules = RuleSet::parse("any => split")?;
+        let mut narrow = Stones::<u64>::from_file("../test_input/day11test.txt")?.with_rules(rules.clone());
+        let mut wide = Stones::<u128>::from_file("../test_input/day11test.txt")?.with_rules(rules);
+        for _ in 0..61 {
+            narrow.update().unwrap();
+            wide.update().unwrap();
+        }
+        assert_eq!(narrow.total(), Ok(5 << 61));
+        assert_eq!(narrow.update(), Err(Overflow::Count));
+        assert_eq!(narrow.total(), Ok(5 << 61));
+        wide.update().unwrap();
+        assert_eq!(wide.total(), Ok(5 << 62));
+
+        let err = Stones::<u64>::from_file("../test_input/day11test_huge.txt").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(Stones::<u128>::from_file("../test_input/day11test_huge.txt").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> std::io::Result<()> {
+        let mut stones = Stones::<u64>::from_file("../test_input/day11test2.txt")?;
+        let mut stats = Vec::new();
+        for _ in 0..3 {
+            stones.update().unwrap();
+            stats.push(stones.stats().unwrap());
+        }
+        assert_eq!(stats, vec![
+            BlinkStats { distinct: 3, max_value: 253000, total: 3 },
+            BlinkStats { distinct: 4, max_value: 14168, total: 4 },
+            BlinkStats { distinct: 5, max_value: 28676032, total: 5 },
+        ]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transitions() -> std::io::Result<()> {
+        let stones = Stones::<u128>::from_file("../test_input/day11test2.txt")?;
+        let transitions = Transitions::discover(&stones, 1000).unwrap();
+        assert_eq!(&transitions.values[..2], &[17, 125]);
+        assert_eq!(&transitions.values[2..5], &[1, 7, 253000]);
+        assert_eq!(transitions.edges[0], vec![(2, 1), (3, 1)]);
+        assert_eq!(transitions.edges[1], vec![(4, 1)]);
+
+        assert_eq!(transitions.count_exact(&stones, 0), Ok(2));
+        assert_eq!(transitions.count_exact(&stones, 25), Ok(55312));
+        assert_eq!(transitions.count_exact(&stones, 75), Ok(65601038650482));
+
+        let p = 1_000_000_007;
+        let mut blinking = Stones::<u128>::from_file("../test_input/day11test2.txt")?;
+        for n in 0..=40 {
+            let exact = transitions.count_exact(&stones, n).unwrap();
+            assert_eq!(transitions.count_mod(&stones, n, p), Some((exact % p as u128) as u32));
+            assert_eq!(blinking.total(), Ok(exact));
+            blinking.update().unwrap();
+        }
+        for n in [75, 120, 200] {
+            let exact = transitions.count_exact(&stones, n).unwrap();
+            assert_eq!(transitions.count_mod(&stones, n, p), Some((exact % p as u128) as u32));
+        }
+        assert!(transitions.count_mod(&stones, 1_000_000_000_000, p).unwrap() < p);
+        assert!(transitions.count_mod(&stones, 1_000_000_000_000, 998_244_353).is_some());
+        assert_eq!(transitions.count_mod(&stones, 1_000_000_000_000, 1 << 20), None);
+        // 561 is a Carmichael number, it passes every Fermat test with a base coprime to it
+        for p in [0, 1, 561, 65_537 * 65_521] {
+            assert_eq!(transitions.count_mod(&stones, 1_000_000_000_000, p), None, "{}", p);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_prime() {
+        let small: Vec<u32> = (0..30).filter(|&p| is_prime(p)).collect();
+        assert_eq!(small, vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert!(is_prime(4_294_967_291));
+        assert!(!is_prime(65_521 * 65_537));
+        assert!(!is_prime(561));
+    }
+
+    #[test]
+    fn test_berlekamp_massey() {
+        // Fibonacci, and powers of 3 after a first term that doesn't fit
+        let fibonacci = [1, 1, 2, 3, 5, 8, 13, 21];
+        assert_eq!(berlekamp_massey(&fibonacci, 101), Some(vec![1, 1]));
+        assert_eq!(power_mod(&[1, 1], 10, 101), vec![34, 55]);
+        assert_eq!(berlekamp_massey(&[0, 0, 0], 7), Some(vec![]));
+        assert_eq!(berlekamp_massey(&[5, 1, 3, 9, 27, 81], 1_000_003), Some(vec![3, 0]));
+        assert_eq!(power_mod(&[3, 0], 5, 1_000_003), vec![0, 81]);
+        assert_eq!(power_mod(&[2], 10, 1_000_003), vec![1024]);
+    }
+
+    #[test]
+    fn test_open_value_set() -> std::io::Result<()> {
+        let stones = Stones::<u64>::from_file("../test_input/day11test.txt")?
+            .with_rules(RuleSet::parse("any => add 1")?);
+        assert_eq!(Transitions::discover(&stones, 100).unwrap_err(), Overflow::ValueSet(100));
+
+        let stones = Stones::<u64>::from_file("../test_input/day11test.txt")?
+            .with_rules(RuleSet::parse("any => split")?);
+        let transitions = Transitions::discover(&stones, 100).unwrap();
+        assert_eq!(transitions.count_exact(&stones, 63), Err(Overflow::Count));
+        assert_eq!(transitions.count_mod(&stones, 63, 7), Some((5 * (1u128 << 63) % 7) as u32));
+        let p = 1_000_000_007;
+        assert_eq!(transitions.count_mod(&stones, 1 << 40, p as u32), Some((5 * power(2, 1 << 40, p) % p) as u32));
+
+        Ok(())
+    }
+
+    fn power(base: u64, exponent: u64, p: u64) -> u64 {
+        (0..u64::BITS - exponent.leading_zeros()).rev()
+            .fold(1, |acc, bit| if (exponent >> bit) & 1 == 1 { acc * acc % p * base % p } else { acc * acc % p })
+    }
 }
@@ -1,189 +1,494 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use itertools::Itertools;
 
-type Crops = HashMap<char, Vec<HashSet<Point>>>;
-
 fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // --eight joins plots that only share a corner into one region as well
+    let connectivity = if args.iter().any(|arg| arg == "--eight") {
+        Connectivity::Eight
+    } else {
+        Connectivity::Four
+    };
+    // --pricing NAME also prices the fences another way, see `pricing_from_name`
+    let mut pricing = None;
+    let mut mode = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--pricing" => pricing = rest.next(),
+            _ if !arg.starts_with("--") => mode = Some(arg.as_str()),
+            _ => {},
+        }
+    }
     let map = Map::from_file("input/day12.txt")?;
+    let regions = Regions::label(&map, connectivity);
+
+    println!("Part 1: {}", part1(&regions));
+    println!("Part 2: {}", part2(&regions));
+    if let Some(name) = pricing {
+        match pricing_from_name(name) {
+            Some(pricing) => println!("Priced by {}: {}", name, total_price(&regions, pricing.as_ref())),
+            None => eprintln!("Unknown pricing {:?}", name),
+        }
+    }
 
-    let mut crops = Crops::new();
-    let mut explored = HashSet::new();
-    let mut current_point = Point{x: 0, y: 0};
-    let mut fringe = VecDeque::new();
+    match mode {
+        Some("report") => print!("{}", report_text(&report(&regions))),
+        Some("json") => println!("{}", report_json(&report(&regions))),
+        Some("svg") => print!("{}", svg(&regions, 20)),
+        _ => {},
+    }
 
-    while explored.len() < map.size() {
-        if explored.contains(&current_point) {
-            current_point = fringe.pop_front().unwrap();
-            continue;
-        }
+    Ok(())
+}
 
-        let c = map.get(&current_point);
-        if !crops.contains_key(&c) {
-            let mut set = HashSet::new();
-            set.insert(current_point);
-            crops.insert(c, vec![set]);
-        } else {
-            let mut found = false;
-            for group in crops.get_mut(&c).unwrap() {
-                if group.iter().any(|point| point.adjacent(&current_point)) {
-                    group.insert(current_point);
-                    found = true;
-                }
-            }
-            if !found {
-                let mut set = HashSet::new();
-                set.insert(current_point);
-                crops.get_mut(&c).unwrap().push(set);
-            }
-        }
+fn part1(regions: &Regions) -> usize {
+    total_price(regions, &AreaPerimeter)
+}
 
-        explored.insert(current_point);
-        for neighbor in map.get_neighbors(&current_point) {
-            if !explored.contains(&neighbor) {
-                fringe.push_back(neighbor);
-            }
-        }
-        current_point = fringe.pop_front().unwrap();
+fn part2(regions: &Regions) -> usize {
+    total_price(regions, &AreaSides)
+}
+
+fn total_price(regions: &Regions, pricing: &dyn FencePricing) -> usize {
+    (0..regions.len()).map(|id| pricing.price(regions, id)).sum()
+}
+
+// How much the fence around one region costs
+trait FencePricing {
+    fn price(&self, regions: &Regions, id: usize) -> usize;
+}
+
+// Area times perimeter, the first part
+struct AreaPerimeter;
+
+impl FencePricing for AreaPerimeter {
+    fn price(&self, regions: &Regions, id: usize) -> usize {
+        regions.area(id) * regions.perimeter(id)
     }
+}
 
-    for (_, regions) in &mut crops {
-        // println!("Checking {}", c);
-        // dbg!(&regions.len());
-        loop {
-            let new_regions = combine_sets(regions);
-            if new_regions.len() == regions.len() {
-                break;
-            }
-            regions.clear();
-            regions.extend(new_regions);
-        }
+// Area times number of sides, the second part
+struct AreaSides;
+
+impl FencePricing for AreaSides {
+    fn price(&self, regions: &Regions, id: usize) -> usize {
+        regions.area(id) * regions.sides(id)
     }
+}
 
-    println!("Part 1: {}", part1(&crops));
-    println!("Part 2: {}", part2(&crops, &map));
+// Area times number of sides, where every side after the first `threshold` costs only
+// `percent` percent, rounded down per region
+struct BulkSides {
+    threshold: usize,
+    percent: usize,
+}
 
-    Ok(())
+impl FencePricing for BulkSides {
+    fn price(&self, regions: &Regions, id: usize) -> usize {
+        let sides = regions.sides(id);
+        let full = sides.min(self.threshold);
+        regions.area(id) * (100 * full + self.percent * (sides - full)) / 100
+    }
 }
 
-fn part1(crops: &Crops) -> usize {
-    let mut result = 0;
-    for regions in crops.values() {
-        for region in regions {
-            let area = area(region);
-            let perimeter = perimeter(region);
-            let product = area * perimeter;
-            result += product;
-        }
+// Area times perimeter, but a fence between two regions is only paid for once, by the one with
+// the lower id. The edge of the map is always paid for.
+struct SharedFences;
+
+impl FencePricing for SharedFences {
+    fn price(&self, regions: &Regions, id: usize) -> usize {
+        let fence = regions.cells[id].iter()
+            .flat_map(|p| p.orthogonal())
+            .filter(|n| regions.label_at(n).is_none_or(|other| other > id))
+            .count();
+        regions.area(id) * fence
+    }
+}
+
+// `perimeter`, `sides`, `shared`, and `bulk` or `bulk:THRESHOLD:PERCENT` (4 sides, then 50%)
+fn pricing_from_name(name: &str) -> Option<Box<dyn FencePricing>> {
+    match name.split(':').collect::<Vec<_>>().as_slice() {
+        ["perimeter"] => Some(Box::new(AreaPerimeter)),
+        ["sides"] => Some(Box::new(AreaSides)),
+        ["shared"] => Some(Box::new(SharedFences)),
+        ["bulk"] => Some(Box::new(BulkSides { threshold: 4, percent: 50 })),
+        ["bulk", threshold, percent] => Some(Box::new(BulkSides {
+            threshold: threshold.parse().ok()?,
+            percent: percent.parse().ok()?,
+        })),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct RegionReport {
+    plant: char,
+    area: usize,
+    perimeter: usize,
+    sides: usize,
+    // Top left and bottom right cells of the bounding box
+    bounds: (Point, Point),
+    holes: usize,
+}
+
+// Every region in id order
+fn report(regions: &Regions) -> Vec<RegionReport> {
+    (0..regions.len())
+        .map(|id| RegionReport {
+            plant: regions.plants[id],
+            area: regions.area(id),
+            perimeter: regions.perimeter(id),
+            sides: regions.sides(id),
+            bounds: regions.bounds(id),
+            holes: regions.holes(id),
+        })
+        .collect()
+}
+
+fn report_text(report: &[RegionReport]) -> String {
+    let mut result = String::new();
+    for (id, region) in report.iter().enumerate() {
+        let (min, max) = region.bounds;
+        result += &format!("{} {}: area {}, perimeter {}, sides {}, bounds {},{} to {},{}, holes {}\n",
+                           id, region.plant, region.area, region.perimeter, region.sides,
+                           min.x, min.y, max.x, max.y, region.holes);
     }
 
     result
 }
 
-fn part2(crops: &Crops, map: &Map) -> usize {
-    let mut result = 0;
+fn report_json(report: &[RegionReport]) -> String {
+    let regions: Vec<String> = report.iter().enumerate()
+        .map(|(id, region)| {
+            let (min, max) = region.bounds;
+            format!("{{\"id\":{},\"plant\":\"{}\",\"area\":{},\"perimeter\":{},\"sides\":{},\
+                     \"bounds\":{{\"min\":{{\"x\":{},\"y\":{}}},\"max\":{{\"x\":{},\"y\":{}}}}},\"holes\":{}}}",
+                    id, json_escape(region.plant), region.area, region.perimeter, region.sides,
+                    min.x, min.y, max.x, max.y, region.holes)
+        })
+        .collect();
+
+    format!("[{}]", regions.join(","))
+}
 
-    for regions in crops.values() {
-        for region in regions {
-            let area = area(region);
-            let corners = region.iter().map(|p| corners(p, region, &map)).sum::<usize>();
-            let product = area * corners;
-            result += product;
+// A character as it goes in a JSON string, anything but quotes, backslashes and control
+// characters as it is
+fn json_escape(c: char) -> String {
+    match c {
+        '"' => "\\\"".to_string(),
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+// The fence around a region as closed polygons through grid vertices (the top left corner of
+// cell x,y is vertex x,y), with a vertex only where the fence turns. The outer boundary runs
+// clockwise on screen and the holes anticlockwise, so the region is always on the right.
+#[derive(Debug, PartialEq)]
+struct Outline {
+    outer: Vec<Point>,
+    holes: Vec<Vec<Point>>,
+}
+
+// All regions as SVG, each filled with a colour for its plant and with its fence drawn
+fn svg(regions: &Regions, cell_size: usize) -> String {
+    let width = regions.labels[0].len() * cell_size;
+    let height = regions.labels.len() * cell_size;
+    let mut result = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                              viewBox=\"0 0 {} {}\">\n", width, height, width, height);
+    for id in 0..regions.len() {
+        let outline = regions.outline(id);
+        let mut path = String::new();
+        for polygon in std::iter::once(&outline.outer).chain(outline.holes.iter()) {
+            let points: Vec<String> = polygon.iter()
+                .map(|p| format!("{} {}", p.x as usize * cell_size, p.y as usize * cell_size))
+                .collect();
+            path += &format!("M{}Z", points.join(" L"));
         }
+        let hue = regions.plants[id] as u32 * 47 % 360;
+        result += &format!("<path d=\"{}\" fill=\"hsl({}, 60%, 70%)\" fill-rule=\"evenodd\" \
+                            stroke=\"black\" stroke-width=\"2\"><title>{} {}</title></path>\n",
+                           path, hue, id, regions.plants[id]);
     }
+    result += "</svg>\n";
 
     result
 }
 
-fn corners(p: &Point, region: &HashSet<Point>, map: &Map) -> usize {
-    if !region.contains(p) {
-        panic!();
+// Which plots with the same plant make up one region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connectivity {
+    // Plots sharing a side, which is what the puzzle answers rely on
+    Four,
+    // Plots sharing a side or a corner
+    Eight,
+}
+
+// Disjoint-set forest over cell indices, with union by size and path halving
+struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(len: usize) -> Self {
+        Self { parent: (0..len).collect(), size: vec![1; len] }
     }
-    let all_neighbors = map.get_neighbors_nodiag(p);
-    let all_neighbors_diag = map.get_neighbors_diag(p);
-
-    let neighbors = all_neighbors.iter()
-        .filter(|neighbor| region.contains(neighbor)).collect_vec();
-    let neighbors_diag = all_neighbors_diag.iter()
-        .filter(|neighbor| region.contains(neighbor)).collect_vec();
-
-    let result = match neighbors.len() {
-        0 => 4,
-        1 => 2,
-        2 => {
-            if neighbors.iter().all(|neighbor| neighbor.x == p.x) || neighbors.iter().all(|neighbor| neighbor.y == p.y) {
-                // middle of a straight piece, no corners
-                0
-            } else {
-                // center of an L
-                if region.contains(&Point{x: neighbors[0].x, y: neighbors[1].y})
-                    && region.contains(&Point{x: neighbors[1].x, y: neighbors[0].y}) {
-                    1
-                } else {
-                    2
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+// The garden split into regions of touching plots with the same plant
+struct Regions {
+    // Region id of every cell, ids are numbered in reading order of each region's first cell
+    labels: Vec<Vec<usize>>,
+    // Plant and cells (in reading order) of every region
+    plants: Vec<char>,
+    cells: Vec<Vec<Point>>,
+    connectivity: Connectivity,
+}
+
+impl Regions {
+    // One pass joining every cell to its neighbours with the same plant, and one numbering the
+    // resulting sets
+    fn label(map: &Map, connectivity: Connectivity) -> Self {
+        let width = map.map[0].len();
+        let height = map.map.len();
+        let mut sets = DisjointSet::new(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let point = Point{x: x as i32, y: y as i32};
+                for n in map.get_neighbors(&point, connectivity) {
+                    if map.get(&n) == map.get(&point) {
+                        sets.union(y * width + x, n.y as usize * width + n.x as usize);
+                    }
                 }
             }
-        },
-        3 => { // T with or without diagonal neighbors
-            let mut result = 2usize;
-            let same_x = neighbors.iter().filter(|neighbor| neighbor.x == p.x).collect_vec();
-            let same_y = neighbors.iter().filter(|neighbor| neighbor.y == p.y).collect_vec();
-            if same_x.len() == 2 {
-                // sideways T or reverse
-                if neighbors_diag.contains(&&Point{x: same_y[0].x, y: same_x[0].y}) {
-                    result -= 1;
-                }
-                if neighbors_diag.contains(&&Point{x: same_y[0].x, y: same_x[1].y}) {
-                    result -= 1;
-                }
-            } else {
-                // proper T or upside down
-                if neighbors_diag.contains(&&Point{x: same_y[0].x, y: same_x[0].y}) {
-                    result -= 1;
+        }
+
+        let mut ids = HashMap::new();
+        let mut labels = vec![vec![0; width]; height];
+        let mut plants = Vec::new();
+        let mut cells: Vec<Vec<Point>> = Vec::new();
+        for (y, row) in labels.iter_mut().enumerate() {
+            for (x, label) in row.iter_mut().enumerate() {
+                let point = Point{x: x as i32, y: y as i32};
+                let root = sets.find(y * width + x);
+                let id = *ids.entry(root).or_insert_with(|| {
+                    plants.push(map.get(&point));
+                    cells.push(Vec::new());
+                    plants.len() - 1
+                });
+                *label = id;
+                cells[id].push(point);
+            }
+        }
+
+        Self { labels, plants, cells, connectivity }
+    }
+
+    fn len(&self) -> usize {
+        self.plants.len()
+    }
+
+    // None outside the map
+    fn label_at(&self, point: &Point) -> Option<usize> {
+        if point.y < 0 || point.x < 0 {
+            return None;
+        }
+        self.labels.get(point.y as usize)
+            .and_then(|row| row.get(point.x as usize))
+            .copied()
+    }
+
+    fn contains(&self, id: usize, point: &Point) -> bool {
+        self.label_at(point) == Some(id)
+    }
+
+    fn area(&self, id: usize) -> usize {
+        self.cells[id].len()
+    }
+
+    // Cell sides that don't face a cell of the same region
+    fn perimeter(&self, id: usize) -> usize {
+        self.cells[id].iter()
+            .map(|p| 4 - p.orthogonal().iter().filter(|n| self.contains(id, n)).count())
+            .sum()
+    }
+
+    fn bounds(&self, id: usize) -> (Point, Point) {
+        let cells = &self.cells[id];
+        let min = Point{x: cells.iter().map(|p| p.x).min().unwrap(), y: cells[0].y};
+        let max = Point{x: cells.iter().map(|p| p.x).max().unwrap(), y: cells[cells.len() - 1].y};
+        (min, max)
+    }
+
+    // Groups of other cells the region surrounds completely. The outside is flooded from a ring
+    // around the bounding box, and whatever is left makes up the holes. Cells outside a
+    // 4-connected region touch even when only their corners do, since the region can't pass
+    // between them. Around an 8-connected one they only touch by sides.
+    fn holes(&self, id: usize) -> usize {
+        let (min, max) = self.bounds(id);
+        let width = (max.x - min.x + 3) as usize;
+        let height = (max.y - min.y + 3) as usize;
+        // Over the bounding box plus the ring, true where the region is or the fill has been
+        let mut filled = vec![vec![false; width]; height];
+        for p in self.cells[id].iter() {
+            filled[(p.y - min.y + 1) as usize][(p.x - min.x + 1) as usize] = true;
+        }
+        let outside = match self.connectivity {
+            Connectivity::Four => Connectivity::Eight,
+            Connectivity::Eight => Connectivity::Four,
+        };
+        let fill = |start: (usize, usize), filled: &mut Vec<Vec<bool>>| {
+            let mut stack = vec![start];
+            filled[start.1][start.0] = true;
+            while let Some((x, y)) = stack.pop() {
+                for n in (Point{x: x as i32, y: y as i32}).neighbors(outside) {
+                    let (nx, ny) = (n.x, n.y);
+                    if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                        continue;
+                    }
+                    if !filled[ny as usize][nx as usize] {
+                        filled[ny as usize][nx as usize] = true;
+                        stack.push((nx as usize, ny as usize));
+                    }
                 }
-                if neighbors_diag.contains(&&Point{x: same_y[1].x, y: same_x[0].y}) {
-                    result -= 1;
+            }
+        };
+
+        fill((0, 0), &mut filled);
+        let mut holes = 0;
+        for y in 0..height {
+            for x in 0..width {
+                if !filled[y][x] {
+                    fill((x, y), &mut filled);
+                    holes += 1;
                 }
             }
-            result
-        },
-        4 => 4 - neighbors_diag.len(),
-        _ => unreachable!(),
-    };
+        }
 
-    // println!("{:?} has {} corners!", p, result);
-    result
-}
+        holes
+    }
 
-fn combine_sets(sets: &Vec<HashSet<Point>>) -> Vec<HashSet<Point>> {
-    let mut regions_coalesced = Vec::new();
-    for (i, region0) in sets.iter().enumerate() {
-        for j in (i+1)..sets.len() {
-            let region1 = &sets[j];
-            if region0.intersection(region1).count() > 0 {
-                let combined: HashSet<Point> = region0.union(region1).cloned().collect();
-                regions_coalesced.push(combined);
-                for (k, region) in sets.iter().enumerate() {
-                    if k != j && k != i {
-                        regions_coalesced.push(region.clone());
-                    }
+    // Every side of a region cell that faces out becomes a fence edge with the region on its
+    // right, and the edges are chained into loops. Where two edges leave the same vertex the
+    // region touches itself there by a corner only: a 4-connected region turns right to stay
+    // with the cell it came from, an 8-connected one turns left to cross over to the other.
+    fn outline(&self, id: usize) -> Outline {
+        let mut edges = Vec::new();
+        let mut outgoing: HashMap<Point, Vec<usize>> = HashMap::new();
+        for p in self.cells[id].iter() {
+            let sides = [
+                (Point{x: p.x, y: p.y - 1}, Point{x: p.x, y: p.y}, (1, 0)),
+                (Point{x: p.x + 1, y: p.y}, Point{x: p.x + 1, y: p.y}, (0, 1)),
+                (Point{x: p.x, y: p.y + 1}, Point{x: p.x + 1, y: p.y + 1}, (-1, 0)),
+                (Point{x: p.x - 1, y: p.y}, Point{x: p.x, y: p.y + 1}, (0, -1)),
+            ];
+            for (facing, start, direction) in sides {
+                if !self.contains(id, &facing) {
+                    outgoing.entry(start).or_default().push(edges.len());
+                    edges.push((start, direction));
                 }
-                return regions_coalesced;
             }
         }
+        let next = |(start, (dx, dy)): (Point, (i32, i32))| {
+            let end = Point{x: start.x + dx, y: start.y + dy};
+            let turns = match self.connectivity {
+                Connectivity::Four => [(-dy, dx), (dx, dy), (dy, -dx)],
+                Connectivity::Eight => [(dy, -dx), (dx, dy), (-dy, dx)],
+            };
+            turns.iter()
+                .find_map(|&turn| outgoing[&end].iter().find(|&&e| edges[e].1 == turn))
+                .copied()
+                .unwrap()
+        };
+
+        let mut used = vec![false; edges.len()];
+        let mut outer = Vec::new();
+        let mut holes = Vec::new();
+        for first in 0..edges.len() {
+            if used[first] {
+                continue;
+            }
+            let mut polygon = Vec::new();
+            let mut e = first;
+            loop {
+                used[e] = true;
+                let following = next(edges[e]);
+                if edges[following].1 != edges[e].1 {
+                    let (start, (dx, dy)) = edges[e];
+                    polygon.push(Point{x: start.x + dx, y: start.y + dy});
+                }
+                e = following;
+                if e == first {
+                    break;
+                }
+            }
+            // Twice the area, positive for clockwise on screen
+            let area: i64 = polygon.iter().zip(polygon.iter().cycle().skip(1))
+                .map(|(a, b)| a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64)
+                .sum();
+            if area > 0 {
+                outer = polygon;
+            } else {
+                holes.push(polygon);
+            }
+        }
+
+        Outline { outer, holes }
     }
-    sets.clone()
-}
 
-fn area(region: &HashSet<Point>) -> usize {
-    region.len()
+    // A region has as many sides as corners
+    fn sides(&self, id: usize) -> usize {
+        self.cells[id].iter().map(|p| corners(p, self, id)).sum()
+    }
 }
 
-fn perimeter(region: &HashSet<Point>) -> usize {
-    4 * region.len() - (2 * region.iter().combinations(2)
-        .filter(|x| x[0].adjacent(x[1])).count())
+// Corners of the region at the four vertices of one of its cells: an outer corner where
+// neither side next to the vertex is in the region, an inner one where both are but the cell
+// across the vertex isn't. Each corner gets counted at exactly one cell. That holds for either
+// connectivity: two cells of an 8-connected region touching only at a vertex both have an outer
+// corner there, and the fence does turn twice at that point.
+fn corners(p: &Point, regions: &Regions, id: usize) -> usize {
+    if !regions.contains(id, p) {
+        panic!();
+    }
+
+    [(1, 1), (1, -1), (-1, 1), (-1, -1)].iter()
+        .filter(|&&(dx, dy)| {
+            let side_x = regions.contains(id, &Point{x: p.x + dx, y: p.y});
+            let side_y = regions.contains(id, &Point{x: p.x, y: p.y + dy});
+            let across = regions.contains(id, &Point{x: p.x + dx, y: p.y + dy});
+            (!side_x && !side_y) || (side_x && side_y && !across)
+        })
+        .count()
 }
 
 struct Map {
@@ -217,38 +522,12 @@ impl Map {
             && point.y < self.map.len() as i32
     }
 
-    fn get_neighbors(&self, start: &Point) -> Vec<Point> {
-        vec![Point{x: start.x + 1, y: start.y},
-             Point{x: start.x - 1, y: start.y},
-             Point{x: start.x, y: start.y - 1},
-             Point{x: start.x, y: start.y + 1},
-             Point{x: start.x + 1, y: start.y + 1}]
-            .into_iter().filter(|p| self.in_bounds(p))
-            .collect()
-    }
-
-    fn get_neighbors_nodiag(&self, start: &Point) -> Vec<Point> {
-        vec![Point{x: start.x + 1, y: start.y},
-             Point{x: start.x - 1, y: start.y},
-             Point{x: start.x, y: start.y - 1},
-             Point{x: start.x, y: start.y + 1}]
-            .into_iter().filter(|p| self.in_bounds(p))
-            .collect()
-    }
-
-    fn get_neighbors_diag(&self, start: &Point) -> Vec<Point> {
-        vec![Point{x: start.x + 1, y: start.y + 1},
-             Point{x: start.x + 1, y: start.y - 1},
-             Point{x: start.x - 1, y: start.y + 1},
-             Point{x: start.x - 1, y: start.y - 1}]
-            .into_iter().filter(|p| self.in_bounds(p))
+    fn get_neighbors(&self, start: &Point, connectivity: Connectivity) -> Vec<Point> {
+        start.neighbors(connectivity).into_iter()
+            .filter(|p| self.in_bounds(p))
             .collect()
     }
 
-    fn size(&self) -> usize {
-        self.map[0].len() * self.map.len()
-    }
-
     fn get(&self, point: &Point) -> char {
         if !self.in_bounds(point) {
             panic!();
@@ -265,27 +544,207 @@ struct Point {
 }
 
 impl Point {
-    fn adjacent(&self, other: &Point) -> bool {
-        if other.y == self.y {
-            if (other.x - self.x).abs() == 1 {
-                return true;
-            }
-            return false;
+    fn orthogonal(&self) -> [Point; 4] {
+        [Point{x: self.x + 1, y: self.y},
+         Point{x: self.x - 1, y: self.y},
+         Point{x: self.x, y: self.y - 1},
+         Point{x: self.x, y: self.y + 1}]
+    }
+
+    fn diagonal(&self) -> [Point; 4] {
+        [Point{x: self.x + 1, y: self.y + 1},
+         Point{x: self.x + 1, y: self.y - 1},
+         Point{x: self.x - 1, y: self.y + 1},
+         Point{x: self.x - 1, y: self.y - 1}]
+    }
+
+    fn neighbors(&self, connectivity: Connectivity) -> Vec<Point> {
+        let mut result = self.orthogonal().to_vec();
+        if connectivity == Connectivity::Eight {
+            result.extend(self.diagonal());
         }
-        if other.x == self.x {
-            if (other.y - self.y).abs() == 1 {
-                return true;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(path: &str) -> std::io::Result<(usize, usize)> {
+        let map = Map::from_file(path)?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        Ok((part1(&regions), part2(&regions)))
+    }
+
+    #[test]
+    fn test_parts() -> std::io::Result<()> {
+        assert_eq!(solve("../test_input/day12test_simple.txt")?, (140, 80));
+        assert_eq!(solve("../test_input/day12test_simple2.txt")?, (772, 436));
+        assert_eq!(solve("../test_input/day12test.txt")?, (1930, 1206));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_labels() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day12test_simple.txt")?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        assert_eq!(regions.labels, vec![
+            vec![0, 0, 0, 0],
+            vec![1, 1, 2, 3],
+            vec![1, 1, 2, 2],
+            vec![4, 4, 4, 2],
+        ]);
+        assert_eq!(regions.plants, vec!['A', 'B', 'C', 'D', 'E']);
+
+        let map = Map::from_file("../test_input/day12test.txt")?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        assert_eq!(regions.len(), 11);
+        assert_eq!(regions.plants.iter().filter(|&&plant| plant == 'C').count(), 2);
+        assert_eq!(regions.plants.iter().filter(|&&plant| plant == 'I').count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_report() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day12test_simple2.txt")?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        let reports = report(&regions);
+        assert_eq!(reports.len(), 5);
+        assert_eq!(reports[0], RegionReport {
+            plant: 'O',
+            area: 21,
+            perimeter: 36,
+            sides: 20,
+            bounds: (Point{x: 0, y: 0}, Point{x: 4, y: 4}),
+            holes: 4,
+        });
+        assert_eq!(reports[1], RegionReport {
+            plant: 'X',
+            area: 1,
+            perimeter: 4,
+            sides: 4,
+            bounds: (Point{x: 1, y: 1}, Point{x: 1, y: 1}),
+            holes: 0,
+        });
+        assert!(report_text(&reports).starts_with("0 O: area 21, perimeter 36, sides 20, bounds 0,0 to 4,4, holes 4\n"));
+        assert!(report_json(&reports).starts_with("[{\"id\":0,\"plant\":\"O\",\"area\":21,\"perimeter\":36,\"sides\":20,\
+            \"bounds\":{\"min\":{\"x\":0,\"y\":0},\"max\":{\"x\":4,\"y\":4}},\"holes\":4},{\"id\":1,"));
+        // JSON escapes rather than Rust ones, everything else as it is
+        for (plant, escaped) in [('é', "é"), ('"', "\\\""), ('\\', "\\\\"), ('\t', "\\t"), ('\u{1}', "\\u0001")] {
+            let json = report_json(&[RegionReport { plant, ..reports[1] }]);
+            assert!(json.starts_with(&format!("[{{\"id\":0,\"plant\":\"{}\",\"area\":1,", escaped)), "{}", json);
+        }
+
+        // A ring of B with an A inside is a hole in the outer A. The C at 5,1 isn't, since it
+        // touches the other C by a corner and the outer A can't close the gap
+        let map = Map::from_file("../test_input/day12test_holes.txt")?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        let holes: Vec<(char, usize)> = report(&regions).iter()
+            .map(|region| (region.plant, region.holes))
+            .collect();
+        assert_eq!(holes, vec![('A', 1), ('B', 1), ('C', 0), ('A', 0), ('C', 0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_connectivity() -> std::io::Result<()> {
+        // The examples only tell the two apart where the two B squares touch by a corner, and
+        // the puzzle answers keep them separate
+        let solve_eight = |path: &str| -> std::io::Result<(usize, usize)> {
+            let regions = Regions::label(&Map::from_file(path)?, Connectivity::Eight);
+            Ok((part1(&regions), part2(&regions)))
+        };
+        assert_eq!(solve_eight("../test_input/day12test_simple.txt")?, (140, 80));
+        assert_eq!(solve_eight("../test_input/day12test_simple2.txt")?, (772, 436));
+        assert_eq!(solve_eight("../test_input/day12test.txt")?, (1930, 1206));
+        assert_eq!(solve("../test_input/day12test_mobius.txt")?, (1184, 368));
+        assert_eq!(solve_eight("../test_input/day12test_mobius.txt")?, (1248, 400));
+
+        // A diamond of A around an empty plot
+        let map = Map::from_file("../test_input/day12test_diagonal.txt")?;
+        let four = Regions::label(&map, Connectivity::Four);
+        let eight = Regions::label(&map, Connectivity::Eight);
+        assert_eq!(four.plants.iter().filter(|&&plant| plant == 'A').count(), 4);
+        assert_eq!(eight.len(), 2);
+        let a = report(&eight).into_iter().find(|region| region.plant == 'A').unwrap();
+        assert_eq!((a.area, a.perimeter, a.sides, a.holes), (4, 16, 16, 1));
+        assert!(report(&four).iter().all(|region| region.holes == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_outline() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day12test_simple2.txt")?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        let outline = regions.outline(0);
+        assert_eq!(outline.outer, vec![Point{x: 5, y: 0}, Point{x: 5, y: 5}, Point{x: 0, y: 5}, Point{x: 0, y: 0}]);
+        assert_eq!(outline.holes.len(), 4);
+        assert_eq!(outline.holes[0], vec![Point{x: 1, y: 1}, Point{x: 1, y: 2}, Point{x: 2, y: 2}, Point{x: 2, y: 1}]);
+        assert_eq!(regions.outline(1).outer, vec![Point{x: 2, y: 1}, Point{x: 2, y: 2}, Point{x: 1, y: 2}, Point{x: 1, y: 1}]);
+
+        // Every corner is a vertex, and every hole has its own polygon
+        for path in ["day12test.txt", "day12test_simple.txt", "day12test_simple2.txt", "day12test_mobius.txt",
+                     "day12test_holes.txt", "day12test_diagonal.txt"] {
+            let map = Map::from_file(format!("../test_input/{}", path))?;
+            for connectivity in [Connectivity::Four, Connectivity::Eight] {
+                let regions = Regions::label(&map, connectivity);
+                for id in 0..regions.len() {
+                    let outline = regions.outline(id);
+                    let vertices = outline.outer.len() + outline.holes.iter().map(Vec::len).sum::<usize>();
+                    assert_eq!(vertices, regions.sides(id));
+                    assert_eq!(outline.holes.len(), regions.holes(id));
+                }
             }
-            return false;
         }
 
-        false
-        // match other {
-        //     Point {x, y } if *x == self.x + 1 && *y == self.y => true,
-        //     Point {x, y } if *x == self.x - 1 && *y == self.y => true,
-        //     Point {x, y } if *x == self.x && *y == self.y + 1 => true,
-        //     Point {x, y } if *x == self.x && *y == self.y - 1 => true,
-        //     _ => false
-        // }
+        // The B squares touch by a corner, which makes one hole in A of 4-connected regions but
+        // two of 8-connected ones
+        let map = Map::from_file("../test_input/day12test_mobius.txt")?;
+        assert_eq!(Regions::label(&map, Connectivity::Four).outline(0).holes.len(), 1);
+        assert_eq!(Regions::label(&map, Connectivity::Eight).outline(0).holes.len(), 2);
+
+        let svg = svg(&Regions::label(&map, Connectivity::Four), 10);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"60\" height=\"60\""));
+        assert_eq!(svg.matches("<path").count(), 3);
+        assert!(svg.contains("<path d=\"M60 0 L60 60 L0 60 L0 0ZM"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pricing() -> std::io::Result<()> {
+        let map = Map::from_file("../test_input/day12test_simple.txt")?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        let price = |name: &str| total_price(&regions, pricing_from_name(name).unwrap().as_ref());
+        assert_eq!(price("perimeter"), 140);
+        assert_eq!(price("sides"), 80);
+        // Only C (area 4) has more than 4 sides, 8 of them: 4 * (4 + 4 / 2)
+        assert_eq!(price("bulk"), 16 + 16 + 24 + 4 + 12);
+        assert_eq!(price("bulk:0:100"), 80);
+        assert_eq!(price("bulk:100:0"), 80);
+        assert_eq!(price("bulk:0:0"), 0);
+        // A pays for all of its 10 fences, B for 6 of its 8, C for 7 of 10, D for 1 of 4 and
+        // E for 4 of 8
+        assert_eq!(price("shared"), 4 * 10 + 4 * 6 + 4 * 7 + 1 + 3 * 4);
+        assert!(pricing_from_name("bulk:x:50").is_none());
+        assert!(pricing_from_name("cheap").is_none());
+
+        // With every fence counted once, each unit of fence is paid for exactly once
+        let map = Map::from_file("../test_input/day12test.txt")?;
+        let regions = Regions::label(&map, Connectivity::Four);
+        let once = total_price(&regions, &SharedFences);
+        assert!(once < part1(&regions));
+        let fences: usize = (0..regions.len())
+            .map(|id| SharedFences.price(&regions, id) / regions.area(id))
+            .sum();
+        let perimeters: usize = (0..regions.len()).map(|id| regions.perimeter(id)).sum();
+        assert_eq!(fences, (perimeters + 2 * (10 + 10)) / 2);
+
+        Ok(())
     }
 }
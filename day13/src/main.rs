@@ -6,12 +6,68 @@ use regex::Regex;
 const PART2_OFFSET: i64 = 10000000000000;
 
 fn main() -> std::io::Result<()> {
-    let f = File::open("input/day13.txt")?;
-    let reader = BufReader::new(f);
-    let lines = reader.lines();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let custom = parse_settings(&args)?;
 
     let parse_start = Instant::now();
 
+    let machines = read_machines("input/day13.txt")?;
+
+    let start = Instant::now();
+    println!("File parse time: {:?}", start.duration_since(parse_start));
+    let part1 = Solver::part1().total(&machines);
+    let part1_end = Instant::now();
+    println!("Part 1: {}, duration: {:?}", part1, part1_end.duration_since(start));
+
+    let part2 = Solver::part2().total(&machines);
+    let part2_end = Instant::now();
+    println!("Part 2: {}, duration: {:?}", part2, part2_end.duration_since(part1_end));
+
+    if let Some(solver) = custom {
+        println!("With {:?}: {}", solver, solver.total(&machines));
+    }
+
+    Ok(())
+}
+
+// --costs A B, --limit N (for both buttons) and --offset N also solve with those settings,
+// starting from the first part's. None without any of them.
+fn parse_settings(args: &[String]) -> std::io::Result<Option<Solver>> {
+    let mut custom = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let solver = custom.unwrap_or_else(Solver::part1);
+        let mut value = || match rest.next() {
+            Some(n) => parse_number(n),
+            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                            format!("{} is missing a value", arg))),
+        };
+        custom = Some(match arg.as_str() {
+            "--costs" => solver.with_costs(value()?, value()?),
+            "--limit" => {
+                let n = value()?;
+                solver.with_limits(Some(n), Some(n))
+            },
+            "--offset" => solver.with_offset(value()?),
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                                format!("unknown option {:?}", arg))),
+        });
+    }
+
+    Ok(custom)
+}
+
+fn parse_number(s: &str) -> std::io::Result<i64> {
+    s.parse().map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?} is not a number or doesn't fit", s))
+    })
+}
+
+fn read_machines(path: &str) -> std::io::Result<Vec<Machine>> {
+    let f = File::open(path)?;
+    let reader = BufReader::new(f);
+    let lines = reader.lines();
+
     let mut machines = Vec::new();
 
     let mut buffer = String::new();
@@ -27,26 +83,7 @@ fn main() -> std::io::Result<()> {
     }
     machines.push(Machine::from_str(&buffer));
 
-    let start = Instant::now();
-    println!("File parse time: {:?}", start.duration_since(parse_start));
-    let part1 = machines.iter()
-        .map(|m| m.min_tokens_to_win())
-        .sum::<i64>();
-    let part1_end = Instant::now();
-    println!("Part 1: {}, duration: {:?}", part1, part1_end.duration_since(start));
-
-    // part2
-    for machine in machines.iter_mut() {
-        machine.prize.0 += PART2_OFFSET;
-        machine.prize.1 += PART2_OFFSET;
-    }
-    let part2 = machines.iter()
-        .map(|m| m.min_tokens_to_win())
-        .sum::<i64>();
-    let part2_end = Instant::now();
-    println!("Part 2: {}, duration: {:?}", part2, part2_end.duration_since(part1_end));
-
-    Ok(())
+    Ok(machines)
 }
 
 #[derive(Debug)]
@@ -58,9 +95,9 @@ struct Machine {
 
 impl Machine {
     fn from_str(s: &str) -> Self {
-        let button_a_re = Regex::new(r"Button A: X\+(?P<x>\d+), Y\+(?P<y>\d+)").unwrap();
-        let button_b_re = Regex::new(r"Button B: X\+(?P<x>\d+), Y\+(?P<y>\d+)").unwrap();
-        let prize_re = Regex::new(r"Prize: X=(?P<x>\d+), Y=(?P<y>\d+)").unwrap();
+        let button_a_re = Regex::new(r"Button A: X(?P<x>[+-]\d+), Y(?P<y>[+-]\d+)").unwrap();
+        let button_b_re = Regex::new(r"Button B: X(?P<x>[+-]\d+), Y(?P<y>[+-]\d+)").unwrap();
+        let prize_re = Regex::new(r"Prize: X=(?P<x>-?\d+), Y=(?P<y>-?\d+)").unwrap();
 
         let button_a_x = button_a_re.captures(s).unwrap()["x"].parse::<i64>().unwrap();
         let button_a_y = button_a_re.captures(s).unwrap()["y"].parse::<i64>().unwrap();
@@ -75,26 +112,308 @@ impl Machine {
             prize: (prize_x, prize_y),
         }
     }
+}
+
+// How the claw machines are played: what each press costs, how often each button may be
+// pressed at most, and how far the prizes are moved along both axes
+#[derive(Debug, Clone, Copy)]
+struct Solver {
+    // Tokens per press of A and B. Negative costs are allowed, but then collinear buttons
+    // might not have a cheapest way to the prize.
+    costs: (i64, i64),
+    limits: (Option<i64>, Option<i64>),
+    offset: i64,
+}
+
+impl Solver {
+    fn new() -> Self {
+        Self { costs: (3, 1), limits: (None, None), offset: 0 }
+    }
+
+    fn with_costs(mut self, a: i64, b: i64) -> Self {
+        self.costs = (a, b);
+        self
+    }
+
+    fn with_limits(mut self, a: Option<i64>, b: Option<i64>) -> Self {
+        self.limits = (a, b);
+        self
+    }
+
+    fn with_offset(mut self, offset: i64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    // No button is pressed more than 100 times
+    fn part1() -> Self {
+        Self::new().with_limits(Some(100), Some(100))
+    }
 
-    fn min_tokens_to_win(&self) -> i64 {
-        let ax = self.button_a.0;
-        let ay = self.button_a.1;
-        let bx = self.button_b.0;
-        let by = self.button_b.1;
-        let zx = self.prize.0;
-        let zy = self.prize.1;
+    fn part2() -> Self {
+        Self::new().with_offset(PART2_OFFSET)
+    }
+
+    // Tokens for all the prizes that can be won
+    fn total(&self, machines: &[Machine]) -> i64 {
+        machines.iter().filter_map(|m| self.tokens(m)).sum()
+    }
 
-        let b_presses = ((ay * zx) - (ax * zy)) / ((bx * ay) - (ax * by));
-        let a_presses = (zx - bx * b_presses) / ax;
+    fn tokens(&self, machine: &Machine) -> Option<i64> {
+        let (a_presses, b_presses) = self.presses(machine)?;
+        a_presses.checked_mul(self.costs.0)?.checked_add(b_presses.checked_mul(self.costs.1)?)
+    }
 
-        let result = (a_presses * ax + b_presses * bx, a_presses * ay + b_presses * by);
+    // The cheapest (A, B) presses that land exactly on the prize within the limits, None if
+    // there are none. With independent buttons Cramer's rule gives the only candidate.
+    // Collinear buttons leave one equation a * ax + b * bx = zx (or the same for y), whose
+    // integer solutions are a one-parameter family from extended Euclid; the cost is linear
+    // along it, so the cheapest one is at an end of the range the limits allow, if the range
+    // ends on that side.
+    fn presses(&self, machine: &Machine) -> Option<(i64, i64)> {
+        let (ax, ay) = (machine.button_a.0 as i128, machine.button_a.1 as i128);
+        let (bx, by) = (machine.button_b.0 as i128, machine.button_b.1 as i128);
+        let zx = machine.prize.0 as i128 + self.offset as i128;
+        let zy = machine.prize.1 as i128 + self.offset as i128;
+        let limits = (self.limits.0.map(i128::from), self.limits.1.map(i128::from));
 
-        if result.0 != self.prize.0 || result.1 != self.prize.1 {
-            0
+        let det = ax * by - ay * bx;
+        let (a_presses, b_presses) = if det != 0 {
+            let a_numerator = zx * by - zy * bx;
+            let b_numerator = ax * zy - ay * zx;
+            if a_numerator % det != 0 || b_numerator % det != 0 {
+                return None;
+            }
+            (a_numerator / det, b_numerator / det)
         } else {
-            a_presses * 3 + b_presses
+            // Both equations are multiples of one of them, the checks below catch a prize
+            // that's off the line
+            let (a_coefficient, b_coefficient, z) = if (ax, bx) != (0, 0) { (ax, bx, zx) } else { (ay, by, zy) };
+            if (a_coefficient, b_coefficient) == (0, 0) {
+                // Neither button moves the claw
+                (0, 0)
+            } else {
+                let costs = (self.costs.0 as i128, self.costs.1 as i128);
+                cheapest_on_line(a_coefficient, b_coefficient, z, costs, limits)?
+            }
+        };
+
+        if a_presses < 0 || b_presses < 0
+            || limits.0.is_some_and(|limit| a_presses > limit)
+            || limits.1.is_some_and(|limit| b_presses > limit)
+            || a_presses * ax + b_presses * bx != zx
+            || a_presses * ay + b_presses * by != zy {
+            return None;
         }
 
+        Some((a_presses.try_into().ok()?, b_presses.try_into().ok()?))
+    }
+}
+
+// gcd(a, b) >= 0 and x, y with a * x + b * y = gcd(a, b)
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        return if a < 0 { (-a, -1, 0) } else { (a, 1, 0) };
+    }
+    let (g, x, y) = extended_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+fn floor_div(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q }
+}
+
+fn ceil_div(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) == (b < 0) { q + 1 } else { q }
+}
+
+// Non-negative a, b up to their limits with a * p + b * q = z (p and q not both 0) and the
+// smallest costs.0 * a + costs.1 * b. None if there's no such pair, or if a negative cost
+// makes the pairs cheaper without end.
+fn cheapest_on_line(p: i128, q: i128, z: i128, costs: (i128, i128),
+                    limits: (Option<i128>, Option<i128>)) -> Option<(i128, i128)> {
+    let (g, x, y) = extended_gcd(p, q);
+    if z % g != 0 {
+        return None;
+    }
+    // a = a0 + t * step_a, b = b0 + t * step_b for any integer t
+    let (a0, b0) = (x * (z / g), y * (z / g));
+    let (step_a, step_b) = (q / g, -p / g);
+
+    let mut lowest: Option<i128> = None;
+    let mut highest: Option<i128> = None;
+    for (start, step, limit) in [(a0, step_a, limits.0), (b0, step_b, limits.1)] {
+        // start + t * step >= 0, and <= limit as -(start + t * step) >= -limit
+        let bounds = std::iter::once((start, step)).chain(limit.map(|limit| (limit - start, -step)));
+        for (start, step) in bounds {
+            match step.signum() {
+                1 => lowest = Some(lowest.map_or(ceil_div(-start, step), |t| t.max(ceil_div(-start, step)))),
+                -1 => highest = Some(highest.map_or(floor_div(-start, step), |t| t.min(floor_div(-start, step)))),
+                _ if start < 0 => return None,
+                _ => {},
+            }
+        }
+    }
+    if let (Some(lowest), Some(highest)) = (lowest, highest) {
+        if lowest > highest {
+            return None;
+        }
+    }
+
+    let slope = costs.0 * step_a + costs.1 * step_b;
+    let t = match slope.signum() {
+        1 => lowest?,
+        -1 => highest?,
+        _ => lowest.or(highest)?,
+    };
+
+    Some((a0 + t * step_a, b0 + t * step_b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(a: (i64, i64), b: (i64, i64), prize: (i64, i64)) -> Machine {
+        Machine { button_a: a, button_b: b, prize }
+    }
+
+    #[test]
+    fn test_example() -> std::io::Result<()> {
+        let machines = read_machines("../test_input/day13test.txt")?;
+        let solver = Solver::part1();
+        let presses: Vec<Option<(i64, i64)>> = machines.iter().map(|m| solver.presses(m)).collect();
+        assert_eq!(presses, vec![Some((80, 40)), None, Some((38, 86)), None]);
+        assert_eq!(solver.total(&machines), 480);
+
+        let solver = Solver::part2();
+        let winnable: Vec<bool> = machines.iter().map(|m| solver.presses(m).is_some()).collect();
+        assert_eq!(winnable, vec![false, true, false, true]);
+        assert_eq!(solver.total(&machines), 875318608908);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_signed() {
+        let m = Machine::from_str("Button A: X-3, Y+2Button B: X+1, Y-1Prize: X=-1, Y=0");
+        assert_eq!((m.button_a, m.button_b, m.prize), ((-3, 2), (1, -1), (-1, 0)));
+        assert_eq!(Solver::new().presses(&m), Some((1, 2)));
+
+        // The only solution presses A -1 times
+        assert_eq!(Solver::new().presses(&machine((1, 0), (0, 1), (-1, 2))), None);
+        assert_eq!(Solver::new().presses(&machine((1, 0), (0, 1), (1, 2))), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_collinear() {
+        // B moves half as far for a third of the cost
+        assert_eq!(Solver::new().presses(&machine((2, 2), (1, 1), (10, 10))), Some((0, 10)));
+        // A moves four times as far for three times the cost, the rest is up to B
+        assert_eq!(Solver::new().presses(&machine((4, 4), (1, 1), (10, 10))), Some((2, 2)));
+        assert_eq!(Solver::new().tokens(&machine((4, 4), (1, 1), (10, 10))), Some(8));
+        // Off the line, and on it but out of reach of steps of 2
+        assert_eq!(Solver::new().presses(&machine((2, 2), (1, 1), (10, 11))), None);
+        assert_eq!(Solver::new().presses(&machine((4, 4), (6, 6), (9, 9))), None);
+        assert_eq!(Solver::new().presses(&machine((4, 4), (6, 6), (10, 10))), Some((1, 1)));
+        // B backs up, so A can overshoot
+        assert_eq!(Solver::new().presses(&machine((2, 2), (-1, -1), (3, 3))), Some((2, 1)));
+        assert_eq!(Solver::new().presses(&machine((-2, -2), (-1, -1), (3, 3))), None);
+        // Only along y
+        assert_eq!(Solver::new().presses(&machine((0, 3), (0, 5), (0, 19))), Some((3, 2)));
+        assert_eq!(Solver::new().presses(&machine((0, 3), (0, 5), (1, 19))), None);
+        // Buttons that don't move the claw
+        assert_eq!(Solver::new().presses(&machine((0, 0), (1, 1), (5, 5))), Some((0, 5)));
+        assert_eq!(Solver::new().presses(&machine((0, 0), (0, 0), (0, 0))), Some((0, 0)));
+        assert_eq!(Solver::new().presses(&machine((0, 0), (0, 0), (1, 0))), None);
+        // Far away
+        assert_eq!(Solver::new().presses(&machine((3, 3), (2, 2), (PART2_OFFSET, PART2_OFFSET))), Some((0, PART2_OFFSET / 2)));
+    }
+
+    #[test]
+    fn test_cheapest_on_line() {
+        // Checked against every split
+        for (p, q) in [(2, 3), (3, 2), (4, 6), (5, -3), (-2, 7), (0, 4), (6, 0)] {
+            for z in -20..40 {
+                for costs in [(3, 1), (1, 3), (2, 2), (-1, 3), (2, -5), (-1, -1)] {
+                    let brute = (0..=60).flat_map(|a| (0..=60).map(move |b| (a, b)))
+                        .filter(|&(a, b)| a * p + b * q == z)
+                        .map(|(a, b)| costs.0 * a + costs.1 * b)
+                        .min();
+                    let found = cheapest_on_line(p, q, z, costs, (None, None));
+                    let capped = cheapest_on_line(p, q, z, costs, (Some(10), Some(20)));
+                    let brute_capped = (0..=10).flat_map(|a| (0..=20).map(move |b| (a, b)))
+                        .filter(|&(a, b)| a * p + b * q == z)
+                        .map(|(a, b)| costs.0 * a + costs.1 * b)
+                        .min();
+                    assert_eq!(capped.map(|(a, b)| costs.0 * a + costs.1 * b), brute_capped);
+                    if costs.0 < 0 || costs.1 < 0 {
+                        continue;
+                    }
+                    assert_eq!(found.map(|(a, b)| costs.0 * a + costs.1 * b), brute, "{} {} {} {:?}", p, q, z, costs);
+                    if let Some((a, b)) = found {
+                        assert!(a >= 0 && b >= 0 && a * p + b * q == z);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let parse = |args: &[&str]| parse_settings(&args.iter().map(|&arg| String::from(arg)).collect::<Vec<_>>());
+        assert!(parse(&[]).unwrap().is_none());
+        let solver = parse(&["--limit", "50", "--costs", "5", "-2"]).unwrap().unwrap();
+        assert_eq!((solver.costs, solver.limits, solver.offset), ((5, -2), (Some(50), Some(50)), 0));
+        let solver = parse(&["--offset", "7"]).unwrap().unwrap();
+        assert_eq!((solver.costs, solver.limits, solver.offset), ((3, 1), (Some(100), Some(100)), 7));
+
+        for args in [&["--limit", "50", "--costs", "5"][..], &["--offset"], &["--limits", "5"], &["5"]] {
+            assert_eq!(parse(args).unwrap_err().kind(), std::io::ErrorKind::InvalidInput, "{:?}", args);
+        }
+        assert_eq!(parse(&["--limit", "x"]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_settings() -> std::io::Result<()> {
+        // The old solver had the first part's costs and no press limits
+        let machines = read_machines("../test_input/day13test.txt")?;
+        assert_eq!(Solver::new().total(&machines), 480);
+        assert_eq!(Solver::new().with_offset(PART2_OFFSET).total(&machines), Solver::part2().total(&machines));
+
+        // More than 100 presses of A, or of B
+        let far = [machine((1, 0), (0, 1), (150, 20)), machine((1, 0), (0, 1), (20, 150))];
+        assert_eq!(Solver::new().total(&far), 450 + 20 + 60 + 150);
+        assert_eq!(Solver::part1().total(&far), 0);
+        assert_eq!(Solver::new().with_limits(Some(150), Some(100)).total(&far), 450 + 20);
+        assert_eq!(Solver::new().with_limits(Some(100), Some(150)).total(&far), 60 + 150);
+
+        // A is the cheaper way along the line, but only 100 presses of it are allowed
+        let line = machine((4, 4), (1, 1), (404, 404));
+        assert_eq!(Solver::new().presses(&line), Some((101, 0)));
+        assert_eq!(Solver::part1().presses(&line), Some((100, 4)));
+        assert_eq!(Solver::part1().tokens(&line), Some(304));
+        assert_eq!(Solver::part1().with_limits(Some(100), Some(3)).presses(&line), None);
+        assert_eq!(Solver::new().with_costs(1, 1).presses(&line), Some((101, 0)));
+        assert_eq!(Solver::new().with_costs(5, 1).presses(&line), Some((0, 404)));
+        assert_eq!(Solver::part1().with_costs(5, 1).presses(&line), Some((76, 100)));
+
+        // Pressing A and B once each gets nowhere, which pays when that costs less than nothing
+        let loop_line = machine((2, 2), (-1, -1), (3, 3));
+        assert_eq!(Solver::new().presses(&loop_line), Some((2, 1)));
+        assert_eq!(Solver::new().with_costs(-1, 3).presses(&loop_line), Some((2, 1)));
+        assert_eq!(Solver::new().with_costs(-3, 1).presses(&loop_line), None);
+        assert_eq!(Solver::part1().with_costs(-3, 1).presses(&loop_line), Some((51, 99)));
+        assert_eq!(Solver::new().with_costs(-1, -1).presses(&line), Some((0, 404)));
+
+        // Offsets move the prize without touching the machine
+        let near = machine((1, 0), (0, 1), (1, 1));
+        assert_eq!(Solver::new().with_offset(-1).presses(&near), Some((0, 0)));
+        assert_eq!(Solver::new().with_offset(9).tokens(&near), Some(40));
+        assert_eq!(near.prize, (1, 1));
 
+        Ok(())
     }
 }